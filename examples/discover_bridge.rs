@@ -1,4 +1,4 @@
-ixtern crate philipshue;
+extern crate philipshue;
 
 mod discover;
 use discover::discover;
@@ -24,10 +24,10 @@ fn main() {
                          id,
                          light.name,
                          if light.state.on { "on" } else { "off" },
-                         light.state.bri,
+                         Show(&light.state.bri),
                          Show(&light.state.hue),
                          Show(&light.state.sat),
-                         Show(&light.state.ct.map(|ct| 1000000u32 / ct as u32)),
+                         Show(&light.state.kelvin()),
                          light.state.alert,
                          Show(&light.state.effect),
                          Show(&light.state.colormode),
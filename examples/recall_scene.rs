@@ -4,6 +4,7 @@ use std::env;
 use std::num::ParseIntError;
 
 use philipshue::bridge::Bridge;
+use philipshue::SceneRecall;
 
 mod discover;
 use discover::discover;
@@ -26,7 +27,7 @@ fn run() -> Result<(), ParseIntError> {
     let group_id: usize = args[2].parse()?;
     let scene = &*args[3];
 
-    match bridge.recall_scene_in_group(group_id, scene) {
+    match bridge.recall_scene_in_group(group_id, &SceneRecall::new(scene)) {
         Ok(resps) => {
             for resp in resps.into_iter() {
                 println!("{:?}", resp)
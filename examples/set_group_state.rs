@@ -4,6 +4,7 @@ use std::env;
 use std::num::ParseIntError;
 
 use philipshue::hue::LightCommand;
+use philipshue::color::kelvin_to_mired;
 use philipshue::bridge::Bridge;
 
 mod discover;
@@ -51,11 +52,14 @@ fn run() -> Result<(), ParseIntError> {
                 .with_sat(254)
         }
         "kelvin" => {
-            cmd.with_ct((1000000u32 / args[4].parse::<u32>()?) as u16)
+            cmd.with_ct(kelvin_to_mired(args[4].parse()?))
                 .with_bri(args[5].parse()?)
                 .with_sat(254)
         }
-        _ => return Ok(println!("Invalid command!")),
+        _ => {
+            println!("Invalid command!");
+            return Ok(());
+        }
     };
 
     match bridge.set_group_state(group_id, &cmd) {
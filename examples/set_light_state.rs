@@ -5,6 +5,7 @@ use std::time::Duration;
 use std::num::ParseIntError;
 
 use philipshue::hue::LightCommand;
+use philipshue::color::kelvin_to_mired;
 use philipshue::bridge::Bridge;
 
 mod discover;
@@ -26,9 +27,9 @@ fn run() -> Result<(), ParseIntError> {
         return Ok(());
     }
     let bridge = Bridge::new(discover().pop().unwrap(), &*args[1]);
-    let input_lights = args[2].split(",")
-        .fold(Ok(Vec::new()),
-              |v, s| v.and_then(|mut v| s.parse::<usize>().map(|n| v.push(n)).map(|_| v)))?;
+    let input_lights = args[2].split(',')
+        .map(|s| s.parse::<usize>())
+        .collect::<Result<Vec<_>, _>>()?;
 
     let cmd = LightCommand::default();
 
@@ -53,11 +54,14 @@ fn run() -> Result<(), ParseIntError> {
                 .with_sat(254)
         }
         "kelvin" => {
-            cmd.with_ct((1000000u32 / args[4].parse::<u32>()?) as u16)
+            cmd.with_ct(kelvin_to_mired(args[4].parse()?))
                 .with_bri(args[5].parse()?)
                 .with_sat(254)
         }
-        _ => return Ok(println!("Invalid command!")),
+        _ => {
+            println!("Invalid command!");
+            return Ok(());
+        }
     };
 
     for id in input_lights.into_iter() {
@@ -1,47 +1,158 @@
-use hyper::tokio;
+use hyper::client::{Client, HttpConnector};
+use hyper::{Body, Method, Request};
+use hyper::rt::{Future, Stream};
+use tokio::runtime::{Builder, Runtime, TaskExecutor};
 
-use hyper::client::{Client, Request};
-use hyper::client::HttpConnector;
-use hyper::client::Builder;
-use hyper::Body;
-
-use std::io::Read;
 use std::collections::BTreeMap;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::ops::Deref;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::thread;
+use std::time::{Duration, Instant};
 
+use serde::Deserialize;
 use serde::de::DeserializeOwned;
-use serde_json::{to_vec, from_slice, from_reader};
+use serde_json::{to_vec, from_slice, from_value};
 
-use crate::errors::{Result, HueError};
+use crate::errors::{Result, ResultExt, HueError, HueErrorKind, BridgeError, classify_network_error, connection_as_unreachable, not_found_as_none};
+use crate::color;
 use crate::hue::*;
 use crate::json::*;
+use crate::success::{BridgeResponse, Delete, UserDeleted};
+use crate::rate_limit::RateLimiter;
+
+/// The N-UPnP endpoint used by `discover()`
+#[cfg(feature = "nupnp")]
+pub const DISCOVERY_URL: &str = "https://discovery.meethue.com/";
+
+/// Attempts to discover bridges using `https://discovery.meethue.com/`
+#[cfg(feature = "nupnp")]
+pub fn discover() -> Result<Vec<Discovery>> {
+    discover_from(DISCOVERY_URL)
+}
 
-/// Attempts to discover bridges using `https://www.meethue.com/api/nupnp`
+/// Runs `discover()` and returns the bridge with the given id, if it was found
+///
+/// The id stays the same even if the IP of the bridge changes, so it can be used to find
+/// a bridge again later.
+#[cfg(feature = "nupnp")]
+pub fn discover_by_id(id: &str) -> Result<Option<Discovery>> {
+    discover().map(|d| d.into_iter().find(|d| d.matches_id(id)))
+}
+
+/// Attempts to discover bridges using the N-UPnP endpoint at the given URL
+///
+/// Useful if the endpoint is mirrored or proxied, otherwise use `discover()`.
 #[cfg(feature = "nupnp")]
-#[tokio::main]
-pub async fn discover() -> Result<Vec<Discovery>, HueError> {
+pub fn discover_from(url: &str) -> Result<Vec<Discovery>> {
+    use tokio::runtime::current_thread;
+
+    current_thread::Runtime::new()?.block_on(discovery_request(url)?)
+}
+
+/// Fetches and parses the response of the N-UPnP endpoint at the given URL
+#[cfg(feature = "nupnp")]
+fn discovery_request(url: &str) -> Result<impl Future<Item = Vec<Discovery>, Error = HueError>> {
     use hyper_tls::HttpsConnector;
 
-    let https = HttpsConnector::new().unwrap();
-    let client = hyper::Client::builder().build::<_, hyper::Body>(https);
+    let https = HttpsConnector::new(1).map_err(|e| HueError::from(e.to_string()))?;
+    let client = Client::builder().build::<_, Body>(https);
+    let uri = url.parse().map_err(|e: hyper::http::uri::InvalidUri| HueError::from(e.to_string()))?;
+    Ok(client.get(uri)
+        .and_then(|response| response.into_body().concat2())
+        .map_err(|e| classify_network_error(e.into()))
+        .and_then(|body| Ok(from_slice(&body)?)))
+}
 
-    let rest =client.get("https://www.meethue.com/api/nupnp"
-               .parse().unwrap()).await?;
+/// Asynchronously discovers bridges using `https://discovery.meethue.com/`
+///
+/// Same as `discover()`, but doesn't block the calling thread. The returned future has to be
+/// run on a tokio runtime.
+#[cfg(feature = "async")]
+pub fn discover_async() -> impl Future<Item = Vec<Discovery>, Error = HueError> {
+    discover_from_async(DISCOVERY_URL)
+}
 
-        println!("Status: {}", res.status());
-        println!("Headers:\n{:#?}", res.headers());
+/// Asynchronously discovers bridges using the N-UPnP endpoint at the given URL
+#[cfg(feature = "async")]
+pub fn discover_from_async(url: &str) -> impl Future<Item = Vec<Discovery>, Error = HueError> {
+    let url = url.to_owned();
+    hyper::rt::lazy(move || discovery_request(&url)).flatten()
+}
+
+/// The port of an address returned by `serve_once()`
+#[cfg(test)]
+fn port_of(address: &str) -> Option<u16> {
+    address.rsplit(':').next().and_then(|port| port.parse().ok())
+}
 
-        let mut body = res.into_body();
-        while let Some(chunk) = body.next().await {
-            let chunk = chunk?;
-            std::io::stdout()
-                .write_all(&chunk)
-                .expect("example expects stdout to work");
+/// Answers the first request to the returned address with `body`, sending back the raw request
+#[cfg(test)]
+fn serve_once(body: &'static str) -> (String, Receiver<String>) {
+    use std::io::{Read, Write};
+    use std::net::TcpListener;
+
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let address = listener.local_addr().unwrap().to_string();
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let (mut stream, _) = listener.accept().unwrap();
+        let mut request = Vec::new();
+        let mut buf = [0; 1024];
+        loop {
+            let read = stream.read(&mut buf).unwrap();
+            request.extend_from_slice(&buf[..read]);
+            let head_end = request.windows(4).position(|w| w == b"\r\n\r\n");
+            if let Some(head_end) = head_end {
+                let head = String::from_utf8_lossy(&request[..head_end]).to_lowercase();
+                let length = head.lines()
+                    .filter_map(|line| line.strip_prefix("content-length:"))
+                    .map(|length| length.trim().parse().unwrap())
+                    .next()
+                    .unwrap_or(0);
+                if request.len() >= head_end + 4 + length {
+                    break;
+                }
+            }
+            if read == 0 {
+                break;
+            }
         }
-        Ok(())
-        .send()
-        .map_err(HueError::from)
-        .and_then(|ref mut r| from_reader(r).map_err(From::from))
+        write!(stream, "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+               body.len(), body).unwrap();
+        sender.send(String::from_utf8_lossy(&request).into_owned()).unwrap();
+    });
+    (address, receiver)
 }
+
+#[test]
+#[cfg(feature = "nupnp")]
+fn discover_from_endpoint() {
+    let (address, requests) = serve_once(r#"[
+        {"id": "001788fffe100491", "internalipaddress": "192.168.2.23"},
+        {"id": "001788fffe09a168", "internalipaddress": "192.168.88.252"}
+    ]"#);
+    let discoveries = discover_from(&format!("http://{}/", address)).unwrap();
+    assert_eq!(discoveries.len(), 2);
+    assert_eq!(discoveries[0].id(), "001788fffe100491");
+    assert_eq!(discoveries[1].ip(), "192.168.88.252");
+    assert!(requests.recv().unwrap().starts_with("GET / HTTP/1.1\r\n"));
+}
+
+#[test]
+#[cfg(feature = "async")]
+fn discover_from_endpoint_async() {
+    let (address, _) = serve_once(r#"[{"id": "001788fffe100491", "internalipaddress": "192.168.2.23"}]"#);
+    let discoveries = Runtime::new().unwrap().block_on(discover_from_async(&format!("http://{}/", address))).unwrap();
+    assert_eq!(discoveries.len(), 1);
+    assert_eq!(discoveries[0].ip(), "192.168.2.23");
+
+    assert!(Runtime::new().unwrap().block_on(discover_from_async("not a url")).is_err());
+}
+
 /// Discovers bridge IP using UPnP
 ///
 /// Waits for about 5 seconds to make sure it gets a response
@@ -64,275 +175,2568 @@ pub fn discover_upnp() -> ::std::result::Result<Vec<String>, ::ssdp::SSDPError>
             .collect()
     })
 }
-/* Tries to register a user, returning the username if successful
+
+/// Tries to register a user, returning the username if successful
 ///
 /// This usually returns a `HueError::BridgeError` saying the link button needs to be pressed.
 /// Therefore it recommended to call this function in a loop:
 /// ## Example
-//```no_run
-// use philipshue::errors::{HueError, HueErrorKind, BridgeError};
-// use philipshue::bridge::{self, Bridge};
-
-// let mut bridge = None;
-// // Discover a bridge
-// let bridge_ip = philipshue::bridge::discover().unwrap().pop().unwrap().into_ip();
-// let devicetype = "my_hue_app#homepc";
-
-// // Keep trying to register a user
-// loop{
-//     match bridge::register_user(&bridge_ip, devicetype){
-//         // A new user has succesfully been registered and the username is returned
-//         Ok(username) => {
-//             bridge = Some(Bridge::new(bridge_ip, username));
-//             break;
-//         },
-//         // Prompt the user to press the link button
-//         Err(HueError(HueErrorKind::BridgeError{error: BridgeError::LinkButtonNotPressed, ..}, _)) => {
-//             println!("Please, press the link on the bridge. Retrying in 5 seconds");
-//             std::thread::sleep(std::time::Duration::from_secs(5));
-//         },
-//         // Some other error happened
-//         Err(e) => {
-//             println!("Unexpected error occured: {:?}", e);
-//             break
-//         }
-//     }
+/// ```no_run
+/// use philipshue::errors::{HueError, HueErrorKind, BridgeError};
+/// use philipshue::bridge::{self, Bridge};
+///
+/// let mut bridge = None;
+/// // Discover a bridge
+/// let bridge_ip = philipshue::bridge::discover().unwrap().pop().unwrap().into_ip();
+/// let devicetype = "my_hue_app#homepc";
+///
+/// // Keep trying to register a user
+/// loop{
+///     match bridge::register_user(&bridge_ip, devicetype){
+///         // A new user has succesfully been registered and the username is returned
+///         Ok(username) => {
+///             bridge = Some(Bridge::new(bridge_ip, username));
+///             break;
+///         },
+///         // Prompt the user to press the link button
+///         Err(HueError(HueErrorKind::BridgeError{error: BridgeError::LinkButtonNotPressed, ..}, _)) => {
+///             println!("Please, press the link on the bridge. Retrying in 5 seconds");
+///             std::thread::sleep(std::time::Duration::from_secs(5));
+///         },
+///         // Some other error happened
+///         Err(e) => {
+///             println!("Unexpected error occured: {:?}", e);
+///             break
+///         }
+///     }
 /// }
-// ```
-*/
-// pub fn register_user(ip: &str, devicetype: &str) -> Result<String> {
-//     let client = Client::new();
-
-//     let body = format!("{{\"devicetype\": {:?}}}", devicetype).as_bytes();
-//     // let body = body.as_bytes();
-//     let url = format!("http://{}/api", ip);
-//     let mut resp = client.post(&url)
-//         .body(Body::BufBody(body, body.len()))
-//         .send()?;
-
-//     from_reader::<_, Vec<HueResponse<User>>>(&mut resp)?
-//         .pop()
-//         .unwrap()
-//         .into_result()
-//         .map(|u| u.username)
-// }
+/// ```
+pub fn register_user(ip: &str, devicetype: &str) -> Result<String> {
+    let body = format!("{{\"devicetype\": {}}}", JsonValue::from(devicetype));
+    register::<User>(ip, body.as_bytes()).map(|u| u.username)
+}
+
+/// Sends a request creating a user to the bridge and returns what the bridge created
+fn register<U: DeserializeOwned>(ip: &str, body: &[u8]) -> Result<U> {
+    let resp = HyperTransport::new().request("POST", &format!("http://{}/api", ip), &BTreeMap::new(), body)?;
+    from_slice::<Vec<HueResponse<U>>>(&resp)?
+        .pop()
+        .ok_or_else(|| HueError::from("Malformed response"))?
+        .into_result()
+}
+
+#[test]
+fn register_new_user() {
+    let (address, requests) = serve_once(r#"[{"success": {"username": "83b7780291a6ceffbe0bd049104df"}}]"#);
+    assert_eq!(register_user(&address, "my_hue_app#homepc").unwrap(), "83b7780291a6ceffbe0bd049104df");
+    let request = requests.recv().unwrap();
+    assert!(request.starts_with("POST /api HTTP/1.1\r\n"));
+    assert!(request.ends_with(r#"{"devicetype": "my_hue_app#homepc"}"#));
+
+    let (address, _) = serve_once(r#"[{"error": {"type": 101, "address": "", "description": "link button not pressed"}}]"#);
+    match register_user(&address, "my_hue_app#homepc") {
+        Err(HueError(HueErrorKind::BridgeError{error: BridgeError::LinkButtonNotPressed, ..}, _)) => (),
+        r => panic!("expected link button not pressed, got {:?}", r),
+    }
+}
+
+/// Tries to register a user like `register_user()`, also requesting a client key
+///
+/// Returns the username and the client key. The client key is the pre-shared key needed
+/// for streaming to the Entertainment API over DTLS.
+pub fn register_user_with_clientkey(ip: &str, devicetype: &str) -> Result<(String, String)> {
+    let body = format!("{{\"devicetype\": {}, \"generateclientkey\": true}}", JsonValue::from(devicetype));
+    register::<UserWithClientKey>(ip, body.as_bytes()).map(|u| (u.username, u.clientkey))
+}
+
+#[test]
+fn register_user_and_clientkey() {
+    let (address, requests) = serve_once(r#"[{"success": {
+        "username": "83b7780291a6ceffbe0bd049104df", "clientkey": "33DDF493992908E3D97FAAA5A5C5F8E7"
+    }}]"#);
+    let (username, clientkey) = register_user_with_clientkey(&address, "my_hue_app#homepc").unwrap();
+    assert_eq!(username, "83b7780291a6ceffbe0bd049104df");
+    assert_eq!(clientkey, "33DDF493992908E3D97FAAA5A5C5F8E7");
+    assert!(requests.recv().unwrap().ends_with(r#"{"devicetype": "my_hue_app#homepc", "generateclientkey": true}"#));
+
+    let (address, _) = serve_once(r#"[{"success": {"username": "83b7780291a6ceffbe0bd049104df"}}]"#);
+    assert!(register_user_with_clientkey(&address, "my_hue_app#homepc").is_err());
+}
+/// Tries to register a user like `register_user()`, returning the client key too if the bridge has one
+///
+/// A client key is requested, but bridges too old for the Entertainment API don't send one.
+/// When the user was created can then be read from its whitelist entry with
+/// `Bridge::new(ip, username).get_current_user()`.
+pub fn register_user_info(ip: &str, devicetype: &str) -> Result<(String, Option<String>)> {
+    let body = format!("{{\"devicetype\": {}, \"generateclientkey\": true}}", JsonValue::from(devicetype));
+    register::<UserInfo>(ip, body.as_bytes()).map(|u| (u.username, u.clientkey))
+}
+
+#[test]
+fn register_user_with_optional_clientkey() {
+    let (address, _) = serve_once(r#"[{"success": {
+        "username": "83b7780291a6ceffbe0bd049104df", "clientkey": "33DDF493992908E3D97FAAA5A5C5F8E7"
+    }}]"#);
+    assert_eq!(register_user_info(&address, "my_hue_app#homepc").unwrap(),
+               ("83b7780291a6ceffbe0bd049104df".to_owned(), Some("33DDF493992908E3D97FAAA5A5C5F8E7".to_owned())));
+
+    let (address, _) = serve_once(r#"[{"success": {"username": "83b7780291a6ceffbe0bd049104df"}}]"#);
+    assert_eq!(register_user_info(&address, "my_hue_app#homepc").unwrap(),
+               ("83b7780291a6ceffbe0bd049104df".to_owned(), None));
+}
+
+
+/// Fetches the part of the configuration of the bridge that can be read without a username
+///
+/// Useful for showing which bridge is being connected to before registering a user.
+pub fn get_public_config(ip: &str) -> Result<PublicConfig> {
+    let resp = HyperTransport::new().request("GET", &format!("http://{}/api/config", ip), &BTreeMap::new(), &[])?;
+    Ok(from_slice(&resp)?)
+}
+
+#[test]
+fn public_config_without_username() {
+    let (address, requests) = serve_once(r#"{
+        "name": "Philips hue", "datastoreversion": "70", "swversion": "1935074050", "apiversion": "1.35.0",
+        "mac": "00:17:88:10:04:91", "bridgeid": "001788FFFE100491", "factorynew": false,
+        "replacesbridgeid": null, "modelid": "BSB002", "starterkitid": ""
+    }"#);
+    let config = get_public_config(&address).unwrap();
+    assert_eq!(config.name, "Philips hue");
+    assert_eq!(config.bridgeid, "001788FFFE100491");
+    assert!(requests.recv().unwrap().starts_with("GET /api/config HTTP/1.1\r\n"));
+}
+
+/// Discovers bridges like `discover()` and fetches the public part of the configuration of
+/// each bridge in parallel
+///
+/// The configuration is `None` for bridges that couldn't be reached.
+#[cfg(feature = "nupnp")]
+pub fn discover_with_config() -> Result<Vec<(Discovery, Option<PublicConfig>)>> {
+    discover().map(|d| attach_configs(d, |ip| get_public_config(ip).ok()))
+}
+
+/// Fetches a configuration for every discovered bridge, each in its own thread
+#[cfg(feature = "nupnp")]
+fn attach_configs<C, F>(discoveries: Vec<Discovery>, fetch: F) -> Vec<(Discovery, Option<C>)>
+where C: Send + 'static, F: Fn(&str) -> Option<C> + Send + Sync + 'static {
+    let fetch = Arc::new(fetch);
+    let handles: Vec<_> = discoveries.iter()
+        .map(|d| {
+            let fetch = fetch.clone();
+            let address = d.address();
+            thread::spawn(move || fetch(&address))
+        })
+        .collect();
+
+    discoveries.into_iter()
+        .zip(handles)
+        .map(|(d, handle)| (d, handle.join().unwrap_or(None)))
+        .collect()
+}
+
+#[test]
+#[cfg(feature = "nupnp")]
+fn attach_configs_in_order() {
+    let discoveries = vec![
+        Discovery { id: "001788fffe100491".to_owned(), internalipaddress: "192.168.2.23".to_owned(), port: None },
+        Discovery { id: "001788fffe09a168".to_owned(), internalipaddress: "192.168.2.24".to_owned(), port: None },
+    ];
+    let attached = attach_configs(discoveries, |address| if address.ends_with("23") {
+        Some(address.to_owned())
+    } else {
+        None
+    });
+
+    assert_eq!(attached[0].0.id(), "001788fffe100491");
+    assert_eq!(attached[0].1.as_deref(), Some("192.168.2.23"));
+    assert_eq!(attached[1].0.id(), "001788fffe09a168");
+    assert_eq!(attached[1].1, None);
+}
+
+#[test]
+#[cfg(feature = "nupnp")]
+fn attach_public_configs() {
+    let (address, _) = serve_once(r#"{
+        "name": "Living room bridge", "datastoreversion": "70", "swversion": "1935074050", "apiversion": "1.35.0",
+        "mac": "00:17:88:10:04:91", "bridgeid": "001788FFFE100491", "factorynew": false,
+        "replacesbridgeid": null, "modelid": "BSB002"
+    }"#);
+    let closed = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let discoveries = vec![
+        Discovery { id: "001788fffe100491".to_owned(), internalipaddress: "127.0.0.1".to_owned(), port: port_of(&address) },
+        Discovery { id: "001788fffe09a168".to_owned(), internalipaddress: "127.0.0.1".to_owned(), port: Some(closed) },
+    ];
+    let attached = attach_configs(discoveries, |address| get_public_config(address).ok());
+    assert_eq!(attached[0].1.as_ref().map(|c| &*c.name), Some("Living room bridge"));
+    assert!(attached[1].1.is_none());
+}
+
+/// The resources fetched by `get_state_parallel()` and the fields of `FullState` they go into
+const FULL_STATE_PARTS: [(&str, &str); 7] = [
+    ("lights", "lights"),
+    ("groups", "groups"),
+    ("scenes", "scenes"),
+    ("config", "config"),
+    ("schedules", "schedule"),
+    ("sensors", "sensors"),
+    ("rules", "rules"),
+];
+
+/// A function inspecting every request before it is sent, given its method, URL and body
+pub type RequestLogger = Box<RequestLog>;
+/// A function inspecting every response, given the URL of the request and the raw body
+pub type ResponseLogger = Box<ResponseLog>;
+/// A function told about every request after it is done, given its URL, how long it took and
+/// whether it succeeded
+pub type MetricsHook = Box<Metrics>;
+
+type RequestLog = dyn Fn(&str, &str, &[u8]) + Send + Sync;
+type ResponseLog = dyn Fn(&str, &[u8]) + Send + Sync;
+type Metrics = dyn Fn(&str, Duration, &::std::result::Result<(), ()>) + Send + Sync;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+/// What is needed to connect to a bridge again later, e.g. after a restart of the app
+pub struct BridgeCredentials {
+    /// The IP of the bridge
+    pub ip: String,
+    /// The registered username
+    pub username: String,
+}
+
+#[test]
+fn credentials_round_trip() {
+    let credentials = BridgeCredentials { ip: "192.168.2.23".to_owned(), username: "hello".to_owned() };
+    let json = ::serde_json::to_string(&credentials).unwrap();
+    assert_eq!(json, r#"{"ip":"192.168.2.23","username":"hello"}"#);
+    assert_eq!(::serde_json::from_str::<BridgeCredentials>(&json).unwrap(), credentials);
+
+    let bridge = Bridge::from_credentials(&credentials);
+    assert_eq!(bridge.get_ip(), "192.168.2.23");
+    assert_eq!(bridge.credentials(), credentials);
+}
+
+/// Handle to a brightness ramp started with `start_brightness_ramp()`
+#[derive(Debug)]
+pub struct BrightnessRamp {
+    stop: Arc<AtomicBool>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl BrightnessRamp {
+    /// Stops sending brightness increments and waits for the last one to finish
+    pub fn stop(self) {
+        self.stop.store(true, Ordering::SeqCst);
+        let _ = self.handle.join();
+    }
+}
+
+/// Finds the brightness of a light reported in the successes of a command
+fn reported_bri(successes: &[JsonMap<String, JsonValue>]) -> Option<u8> {
+    successes.iter()
+        .flat_map(|s| s.iter())
+        .filter(|&(key, _)| key.ends_with("/bri"))
+        .filter_map(|(_, value)| value.as_u64())
+        .map(|bri| bri as u8)
+        .next()
+}
+
+/// Keeps sending `delta` until stopped, a send fails or the brightness hits its limit
+fn run_ramp<F>(delta: i16, interval: Duration, stop: &AtomicBool, mut send: F)
+where F: FnMut(i16) -> Result<Vec<JsonMap<String, JsonValue>>> {
+    while !stop.load(Ordering::SeqCst) {
+        let bri = match send(delta) {
+            Ok(successes) => reported_bri(&successes),
+            Err(_) => return,
+        };
+        match bri {
+            Some(254) if delta > 0 => return,
+            Some(1) if delta < 0 => return,
+            _ => thread::sleep(interval),
+        }
+    }
+}
+
+#[test]
+fn ramp_stops_at_maximum() {
+    let stop = AtomicBool::new(false);
+    let mut bri = 200i16;
+    let mut steps = 0;
+    run_ramp(20, Duration::from_millis(0), &stop, |delta| {
+        steps += 1;
+        bri = (bri + delta).min(254);
+        let mut success = JsonMap::new();
+        success.insert("/lights/1/state/bri".to_owned(), JsonValue::from(bri));
+        Ok(vec![success])
+    });
+    assert_eq!(bri, 254);
+    assert_eq!(steps, 3);
+}
 
+#[derive(Debug, Clone)]
+/// A change of the state of a light, sent by `watch_lights()`
+pub struct LightChange {
+    /// The ID of the light
+    pub id: usize,
+    /// The state before the change
+    pub old: LightState,
+    /// The state after the change
+    pub new: LightState,
+}
+
+/// Handle to the polling thread started with `watch_lights()`
+///
+/// Dereferences to the `Receiver` of the changes. Polling stops when the handle is dropped or
+/// `stop()` is called.
 #[derive(Debug)]
+pub struct LightWatch {
+    changes: Receiver<LightChange>,
+    stop: Sender<()>,
+    handle: thread::JoinHandle<()>,
+}
+
+impl LightWatch {
+    /// Stops polling and waits for the poll in progress, if any, to finish
+    pub fn stop(self) {
+        drop(self.stop);
+        let _ = self.handle.join();
+    }
+}
+
+impl Deref for LightWatch {
+    type Target = Receiver<LightChange>;
+
+    fn deref(&self) -> &Receiver<LightChange> {
+        &self.changes
+    }
+}
+
+/// The changes of the states of lights that are in both `old` and `new`
+fn light_changes(old: &BTreeMap<usize, Light>, new: &BTreeMap<usize, Light>) -> Vec<LightChange> {
+    new.iter()
+        .filter_map(|(&id, light)| old.get(&id).map(|old| (id, &old.state, &light.state)))
+        .filter(|&(_, old, new)| old != new)
+        .map(|(id, old, new)| LightChange { id, old: old.clone(), new: new.clone() })
+        .collect()
+}
+
+/// Whether the address is an IP address or a hostname, optionally followed by a port
+///
+/// IPv6 addresses have to be in brackets like in a URL, e.g. `[fe80::1]` or `[fe80::1]:80`.
+fn is_valid_address(address: &str) -> bool {
+    if address.parse::<Ipv4Addr>().is_ok() || address.parse::<SocketAddr>().is_ok() {
+        return true;
+    }
+    if address.starts_with('[') && address.ends_with(']') {
+        return address[1..address.len() - 1].parse::<Ipv6Addr>().is_ok();
+    }
+    let host = match address.rfind(':') {
+        Some(i) if address[i + 1..].parse::<u16>().is_ok() => &address[..i],
+        Some(_) => return false,
+        None => address,
+    };
+    !host.is_empty() && host.len() <= 253 && host.split('.').all(|label| {
+        !label.is_empty() && label.len() <= 63 &&
+            !label.starts_with('-') && !label.ends_with('-') &&
+            label.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    })
+}
+
+#[test]
+fn bridge_addresses() {
+    assert!(is_valid_address("192.168.2.23"));
+    assert!(is_valid_address("192.168.2.23:8080"));
+    assert!(is_valid_address("[fe80::1]"));
+    assert!(is_valid_address("[fe80::1]:80"));
+    assert!(is_valid_address("philips-hue.local"));
+    assert!(is_valid_address("hue-bridge:8080"));
+    assert!(!is_valid_address(""));
+    assert!(!is_valid_address("http://192.168.2.23"));
+    assert!(!is_valid_address("192.168.2.23/api"));
+    assert!(!is_valid_address("hue bridge"));
+    assert!(!is_valid_address("-bridge.local"));
+    assert!(!is_valid_address("bridge:port"));
+    // Without brackets the last part would be taken for the port in the URL
+    assert!(!is_valid_address("fe80::1"));
+    assert!(!is_valid_address("::1"));
+    assert!(!is_valid_address("[bridge]"));
+}
+
+/// ID of the special group containing all lights known to the bridge
+pub const ALL_LIGHTS_GROUP: usize = 0;
+
 /// The bridge connection
-pub struct Bridge {
-    client: (Client<HttpConnector>),
+///
+/// Clones share the transport, so they can be moved to other threads and still send their
+/// requests through the same connections.
+///
+/// Connections to the bridge are kept alive and reused by later requests, also by clones of
+/// the `Bridge`. Use `with_pool_size()` to change how many are kept open.
+pub struct Bridge<T = HyperTransport> {
+    transport: Arc<T>,
+    url: String,
+    bridge_id: Option<String>,
+    headers: BTreeMap<String, String>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    request_logger: Option<Arc<RequestLog>>,
+    response_logger: Option<Arc<ResponseLog>>,
+    metrics_hook: Option<Arc<Metrics>>,
+    auto_on: bool,
+}
+
+impl<T> Clone for Bridge<T> {
+    fn clone(&self) -> Self {
+        Bridge {
+            transport: self.transport.clone(),
+            url: self.url.clone(),
+            bridge_id: self.bridge_id.clone(),
+            headers: self.headers.clone(),
+            rate_limiter: self.rate_limiter.clone(),
+            request_logger: self.request_logger.clone(),
+            response_logger: self.response_logger.clone(),
+            metrics_hook: self.metrics_hook.clone(),
+            auto_on: self.auto_on,
+        }
+    }
+}
+
+impl<T> fmt::Debug for Bridge<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Bridge")
+            .field("url", &self.url)
+            .field("bridge_id", &self.bridge_id)
+            .field("headers", &self.headers)
+            .finish()
+    }
+}
+
+/// Sends the HTTP requests of a `Bridge`
+///
+/// `HyperTransport` is used by default. Other implementations can e.g. answer with canned
+/// responses to test code using a `Bridge` without a real bridge, see `Bridge::with_transport()`.
+/// A transport is shared by all clones of a `Bridge`, which may be used from several threads
+/// at once.
+pub trait Transport: Send + Sync {
+    /// Sends a request with the headers and body and returns the body of the response
+    fn request(&self, method: &str, url: &str, headers: &BTreeMap<String, String>, body: &[u8]) -> Result<Vec<u8>>;
+}
+
+#[derive(Debug)]
+/// Sends requests using a hyper `Client`, driven by a runtime started with the first request
+pub struct HyperTransport {
+    client: Client<HttpConnector>,
+    runtime: Mutex<Option<Runtime>>,
+}
+
+impl<T: Transport> Bridge<T> {
+    /// Creates a `Bridge` on the given IP with the given username, sending requests through `transport`
+    pub fn with_transport<S: Into<String>, U: Into<String>>(transport: T, ip: S, username: U) -> Self {
+        Bridge {
+            transport: Arc::new(transport),
+            url: format!("http://{}/api/{}/", ip.into(), username.into()),
+            bridge_id: None,
+            headers: BTreeMap::new(),
+            rate_limiter: None,
+            request_logger: None,
+            response_logger: None,
+            metrics_hook: None,
+            auto_on: false,
+        }
+    }
+
+    fn call<R: DeserializeOwned>(&self, method: &str, url: &str, body: &[u8]) -> Result<R> {
+        match self.metrics_hook {
+            Some(ref hook) => {
+                let start = Instant::now();
+                let result = self.call_timed(method, url, body);
+                hook(url, start.elapsed(), &result.as_ref().map(|_| ()).map_err(|_| ()));
+                result
+            }
+            None => self.call_timed(method, url, body),
+        }
+    }
+
+    fn call_timed<R: DeserializeOwned>(&self, method: &str, url: &str, body: &[u8]) -> Result<R> {
+        if method != "GET" {
+            if let Some(ref limiter) = self.rate_limiter {
+                limiter.wait();
+            }
+        }
+        if let Some(ref logger) = self.request_logger {
+            logger(method, url, body);
+        }
+        let buf = self.transport.request(method, url, &self.headers, body)?;
+        if let Some(ref logger) = self.response_logger {
+            logger(url, &buf);
+        }
+
+        // Errors are usually wrapped in an array, but some bridges and emulators send a bare object
+        from_slice(&buf).or_else(|_| match from_slice::<Vec<HueResponse<R>>>(&buf) {
+            Ok(responses) => collect_results(responses)?
+                .into_iter()
+                .next()
+                .ok_or_else(|| "Malformed response".into()),
+            Err(e) => from_slice::<HueResponse<R>>(&buf)
+                .map_err(|_| HueError::with_chain(e, HueErrorKind::DeserializeFailed { body: String::from_utf8_lossy(&buf).into_owned() }))?
+                .into_result(),
+        })
+    }
+}
+
+/// A request recorded by `StubTransport`
+#[cfg(test)]
+#[derive(Debug, Clone, PartialEq)]
+struct StubRequest {
+    method: String,
     url: String,
+    body: String,
 }
 
-// fn send_with_body<'a, T: DeserializeOwned>(rb: Builder, body: &'a [u8]) -> Result<T> {
-//     send(rb.body(Body::BufBody(body, body.len())))
-// }
-
-// fn send<T: DeserializeOwned>(rb: Builder) -> Result<T> {
-//     rb.send()
-//         .map_err(HueError::from)
-//         .and_then(|ref mut resp| {
-//             let mut buf = Vec::new();
-//             resp.read_to_end(&mut buf)?;
-
-//             from_slice(&buf).or_else(|_| {
-//                 from_slice::<Vec<HueResponse<T>>>(&buf)?
-//                     .into_iter()
-//                     .next()
-//                     .ok_or_else(|| "Malformed response".into())
-//                     .and_then(HueResponse::into_result)
-//             })
-//         })
-// }
-
-// #[test]
-// fn get_ip_and_username() {
-//     let b = Bridge::new("test", "hello");
-//     assert_eq!(b.get_ip(), "test");
-//     assert_eq!(b.get_username(), "hello");
-// }
-
-// /// Many commands on the bridge return an array of things that were succesful.
-// /// This is a type alias for that type.
-// pub type SuccessVec = Vec<JsonMap<String, JsonValue>>;
-
-// use serde::Deserialize;
-// //use hyper::client::Builder;
-
-// fn extract<'a, T: Deserialize<'a>>(responses: Vec<HueResponse<T>>) -> Result<Vec<T>> {
-//     let mut res_v = Vec::with_capacity(responses.len());
-//     for val in responses {
-//         res_v.push(val.into_result()?)
-//     }
-//     Ok(res_v)
-// }
-
-// impl Bridge {
-//     /// Creates a `Bridge` on the given IP with the given username
-//     pub fn new<S: Into<String>, U: Into<String>>(ip: S, username: U) -> Self {
-//         Bridge {
-//             client: Client::new(),
-//             url: format!("http://{}/api/{}/", ip.into(), username.into()),
-//         }
-//     }
-//     /// Gets the IP of bridge
-//     pub fn get_ip(&self) -> &str {
-//         self.url.split('/').nth(2).unwrap()
-//     }
-//     /// Gets the username this `Bridge` uses
-//     pub fn get_username(&self) -> &str {
-//         self.url.split('/').nth(4).unwrap()
-//     }
-//     /// Gets all lights that are connected to the bridge
-//     pub fn get_all_lights(&self) -> Result<BTreeMap<usize, Light>> {
-//         send(self.client.get(&format!("{}lights", self.url)))
-//     }
-//     /// Gets the light with the specific id
-//     pub fn get_light(&self, id: usize) -> Result<Light> {
-//         send(self.client.get(&format!("{}lights/{}", self.url, id)))
-//     }
-//     /// Gets all the light that were found last time a search for new lights was done
-//     pub fn get_new_lights(&self) -> Result<BTreeMap<usize, Light>> {
-//         // TODO return lastscan too
-//         send(self.client.get(&format!("{}lights/new", self.url)))
-//     }
-//     /// Makes the bridge search for new lights (and switches).
-//     ///
-//     /// The found lights can be retrieved with `get_new_lights()`
-//     pub fn search_for_new_lights(&self) -> Result<SuccessVec> {
-//         // TODO Allow deviceids to be specified
-//         send(self.client.post(&format!("{}lights", self.url))).and_then(extract)
-//     }
-//     /// Sets the state of a light by sending a `LightCommand` to the bridge for this light
-//     pub fn set_light_state(&self, id: usize, command: &LightCommand) -> Result<SuccessVec> {
-//         send_with_body(self.client.put(&format!("{}lights/{}/state", self.url, id)),
-//                        &to_vec(command)?)
-//             .and_then(extract)
-//     }
-//     /// Renames the light
-//     pub fn rename_light(&self, id: usize, name: String) -> Result<SuccessVec> {
-//         let mut name_map = BTreeMap::new();
-//         name_map.insert("name".to_owned(), name);
-//         send_with_body(self.client.put(&format!("{}lights/{}", self.url, id)),
-//                        &to_vec(&name_map)?)
-//             .and_then(extract)
-//     }
-//     /// Deletes a light from the bridge
-//     pub fn delete_light(&self, id: usize) -> Result<SuccessVec> {
-//         send(self.client.delete(&format!("{}lights/{}", self.url, id))).and_then(extract)
-//     }
-
-//     // GROUPS
-
-//     /// Gets all groups of the bridge
-//     pub fn get_all_groups(&self) -> Result<BTreeMap<usize, Group>> {
-//         send(self.client.get(&format!("{}groups", self.url)))
-//     }
-//     /// Creates a group and returns the ID of the group
-//     pub fn create_group(&self, name: String, lights: Vec<usize>, group_type: GroupType, room_class: Option<RoomClass>) -> Result<usize> {
-//         let g = Group {
-//             name: name,
-//             lights: lights,
-//             group_type: group_type,
-//             class: room_class,
-//             recycle: None,
-//             state: None,
-//             action: None,
-//         };
-//         let r: HueResponse<Id<usize>> = send_with_body(self.client.post(&format!("{}groups", self.url)),
-//                                                      &to_vec(&g)?)?;
-//         r.into_result().map(|g| g.id)
-//     }
-//     /// Gets extra information about a specific group
-//     pub fn get_group_attributes(&self, id: usize) -> Result<Group> {
-//         send(self.client.get(&format!("{}groups/{}", self.url, id)))
-//     }
-//     /// Set the name, light and class of a group
-//     pub fn set_group_attributes(&self, id: usize, attr: &GroupCommand) -> Result<SuccessVec> {
-//         send_with_body(self.client.put(&format!("{}groups/{}", self.url, id)),
-//                        &to_vec(attr)?)
-//             .and_then(extract)
-//     }
-//     /// Sets the state of all lights in the group.
-//     ///
-//     /// ID 0 is a sepcial group containing all lights known to the bridge
-//     pub fn set_group_state(&self, id: usize, state: &LightCommand) -> Result<SuccessVec> {
-//         send_with_body(self.client.put(&format!("{}groups/{}/action", self.url, id)),
-//                        &to_vec(state)?)
-//             .and_then(extract)
-//     }
-//     /// Deletes the specified group
-//     ///
-//     /// It's not allowed to delete groups of type `LightSource` or `Luminaire`.
-//     pub fn delete_group(&self, id: usize) -> Result<Vec<String>> {
-//         send(self.client.delete(&format!("{}groups/{}", self.url, id))).and_then(extract)
-//     }
-
-//     // CONFIGURATION
-
-//     /// Returns detailed information about the configuration of the bridge.
-//     pub fn get_configuration(&self) -> Result<Configuration> {
-//         send(self.client.get(&format!("{}config", self.url)))
-//     }
-//     /// Sets some configuration values.
-//     pub fn modify_configuration(&self, command: &ConfigurationModifier) -> Result<SuccessVec> {
-//         send_with_body(self.client.put(&format!("{}config", self.url)),
-//                        &to_vec(command)?)
-//             .and_then(extract)
-//     }
-//     /// Deletes the specified user removing them from the whitelist.
-//     pub fn delete_user(&self, username: &str) -> Result<Vec<String>> {
-//         send(self.client.delete(&format!("{}config/whitelist/{}", self.url, username)))
-//             .and_then(extract)
-//     }
-//     /// Fetches the entire datastore from the bridge.
-//     ///
-//     /// This is a resource intensive command for the bridge, and should therefore be used sparingly.
-//     pub fn get_full_state(&self) -> Result<FullState> {
-//         send(self.client.get(&self.url))
-//     }
-
-//     /// Sets the state of lights in the group to the state in the scene
-//     ///
-//     /// Note that this will affect that are both in the group and in the scene.
-//     /// Using group 0 will set all the lights in the scene, since group 0 is a special
-//     /// group that contains all lights
-//     pub fn recall_scene_in_group(&self, group_id: usize, scene_id: &str) -> Result<SuccessVec> {
-//         send_with_body(self.client.put(&format!("{}groups/{}/action", self.url, group_id)),
-//                        &to_vec(&SceneRecall{scene: scene_id})?)
-//             .and_then(extract)
-//     }
-
-//     // SCENES
-
-//     /// Gets all scenes of the bridge
-//     pub fn get_all_scenes(&self) -> Result<BTreeMap<String, Scene>> {
-//         send(self.client.get(&format!("{}scenes", self.url)))
-//     }
-//     /// Creates a scene on the bridge and returns the ID of the created scene.
-//     pub fn create_scene(&self, scene: &SceneCreater) -> Result<String> {
-//         let r: HueResponse<Id<String>> = send_with_body(self.client.post(&format!("{}scenes", self.url)),
-//                                                         &to_vec(scene)?)?;
-//         r.into_result().map(|g| g.id)
-//     }
-//     /// Sets general things in the specified scene
-//     pub fn modify_scene(&self, id: &str, scene: &SceneModifier) -> Result<SuccessVec> {
-//         send_with_body(self.client.put(&format!("{}scenes/{}", self.url, id)), &to_vec(scene)?)
-//             .and_then(extract)
-//     }
-//     /// Sets the light state of the specified ID that is stored in the scene
-//     pub fn set_light_state_in_scene(&self, scene_id: &str, light_id: usize,
-//         state: &LightStateChange) -> Result<SuccessVec> {
-
-//         send_with_body(self.client.put(&format!("{}scenes/{}/lightstates/{}", self.url,
-//             scene_id, light_id)), &to_vec(state)?).and_then(extract)
-//     }
-//     /// Deletes the specified scene
-//     pub fn delete_scene(&self, id: &str) -> Result<Vec<String>> {
-//         send(self.client.delete(&format!("{}scenes/{}", self.url, id))).and_then(extract)
-//     }
-//     /// Gets the scene with the specified ID with its `lightstates`
-//     pub fn get_scene_with_states(&self, id: &str) -> Result<Scene> {
-//         send(self.client.get(&format!("{}scenes/{}", self.url, id)))
-//     }
-// }
+#[cfg(test)]
+impl<'a> From<(&'a str, &'a str, &'a str)> for StubRequest {
+    fn from((method, url, body): (&str, &str, &str)) -> Self {
+        StubRequest { method: method.to_owned(), url: url.to_owned(), body: body.to_owned() }
+    }
+}
+
+/// Answers requests with canned responses and records them, to test a `Bridge` without a bridge
+#[cfg(test)]
+struct StubTransport {
+    responses: Mutex<Vec<&'static str>>,
+    routes: BTreeMap<String, &'static str>,
+    requests: Mutex<Vec<StubRequest>>,
+}
+
+#[cfg(test)]
+impl StubTransport {
+    /// Answers the requests with the responses in order, repeating the last one
+    fn with_responses(responses: &[&'static str]) -> Self {
+        StubTransport { responses: Mutex::new(responses.to_vec()), routes: BTreeMap::new(), requests: Mutex::new(Vec::new()) }
+    }
+    /// Answers requests to each URL with its own response, whatever order they come in
+    fn with_routes(routes: &[(&str, &'static str)]) -> Self {
+        StubTransport {
+            routes: routes.iter().map(|&(url, response)| (url.to_owned(), response)).collect(),
+            ..StubTransport::with_responses(&[])
+        }
+    }
+    /// The requests sent so far
+    fn requests(&self) -> Vec<StubRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+impl Transport for StubTransport {
+    fn request(&self, method: &str, url: &str, _: &BTreeMap<String, String>, body: &[u8]) -> Result<Vec<u8>> {
+        self.requests.lock().unwrap().push((method, url, &*String::from_utf8_lossy(body)).into());
+        if let Some(response) = self.routes.get(url) {
+            return Ok(response.as_bytes().to_vec());
+        }
+        let mut responses = self.responses.lock().unwrap();
+        let response = if responses.len() > 1 { responses.remove(0) } else { responses[0] };
+        Ok(response.as_bytes().to_vec())
+    }
+}
+
+/// A `Bridge` on 192.168.2.23 with the username "hello", answering with the responses in order
+#[cfg(test)]
+fn stub_bridge(responses: &[&'static str]) -> Bridge<StubTransport> {
+    Bridge::with_transport(StubTransport::with_responses(responses), "192.168.2.23", "hello")
+}
+
+#[test]
+fn stub_transport() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/lights/1/state/on": true}}]"#]);
+    let successes = bridge.set_light_state(1, &LightCommand::default().on()).unwrap();
+    assert_eq!(successes[0]["/lights/1/state/on"], JsonValue::Bool(true));
+    assert_eq!(bridge.transport.requests(), vec![("PUT", "http://192.168.2.23/api/hello/lights/1/state", r#"{"on":true}"#).into()]);
+
+    let bridge = stub_bridge(&[r#"[{"error": {
+        "type": 201, "address": "/lights/1/state/bri", "description": "parameter, bri, is not modifiable. Device is set to off."
+    }}]"#]);
+    match bridge.set_light_state(1, &LightCommand::default().with_bri(100)) {
+        Err(HueError(HueErrorKind::BridgeError{error: BridgeError::DeviceIsSetToOff, ..}, _)) => (),
+        r => panic!("expected device set to off, got {:?}", r),
+    }
+}
+
+#[test]
+fn bare_and_wrapped_errors() {
+    let bare = r#"{"error": {"type": 3, "address": "/lights/9", "description": "resource, /lights/9, not available"}}"#;
+    let wrapped = r#"[{"error": {"type": 3, "address": "/lights/9", "description": "resource, /lights/9, not available"}}]"#;
+    for &body in &[bare, wrapped] {
+        match stub_bridge(&[body]).get_light(9) {
+            Err(HueError(HueErrorKind::BridgeError{error: BridgeError::ResourceNotAvailable, ref address, ..}, _)) => {
+                assert_eq!(address, "/lights/9")
+            }
+            r => panic!("expected unavailable resource for {}, got {:?}", body, r),
+        }
+    }
+}
+
+#[test]
+fn last_scan() {
+    let bridge = stub_bridge(&[r#"{"7": {"name": "Hue Lamp 7"}, "lastscan": "2012-10-29T12:00:00"}"#]);
+    let scan = bridge.get_last_scan().unwrap();
+    assert_eq!(scan, ScanState::Done("2012-10-29T12:00:00".to_owned()));
+    assert_eq!(bridge.transport.requests()[0], ("GET", "http://192.168.2.23/api/hello/lights/new", "").into());
+    #[cfg(feature = "chrono")]
+    {
+        use chrono::{TimeZone, Utc};
+        assert_eq!(scan.timestamp(), Utc.with_ymd_and_hms(2012, 10, 29, 12, 0, 0).single());
+    }
+
+    assert_eq!(stub_bridge(&[r#"{"lastscan": "active"}"#]).get_last_scan().unwrap(), ScanState::Active);
+    assert!(stub_bridge(&["{}"]).get_last_scan().is_err());
+}
+
+#[test]
+fn typed_and_plain_ids() {
+    use std::convert::TryFrom;
+
+    let on = r#"[{"success": {"/lights/3/state/on": true}}]"#;
+    let bridge = stub_bridge(&[on, on, on, r#"[{"success": "/scenes/ab341ef24 deleted"}]"#, on]);
+    let light = LightId::try_from(3).unwrap();
+    bridge.set_light_state(light, &LightCommand::default().on()).unwrap();
+    bridge.set_light_state("3", &LightCommand::default().on()).unwrap();
+    bridge.set_group_state(GroupId::from(2), &LightCommand::default().on()).unwrap();
+    bridge.delete_scene(SceneId::try_from("ab341ef24").unwrap()).unwrap();
+    bridge.recall_scene_in_group_with_transition("2", "ab341ef24", 20).unwrap();
+    let urls: Vec<_> = bridge.transport.requests().into_iter().map(|r| r.url).collect();
+    assert_eq!(urls, vec!["http://192.168.2.23/api/hello/lights/3/state",
+                          "http://192.168.2.23/api/hello/lights/3/state",
+                          "http://192.168.2.23/api/hello/groups/2/action",
+                          "http://192.168.2.23/api/hello/scenes/ab341ef24",
+                          "http://192.168.2.23/api/hello/groups/2/action"]);
+
+    let bridge = stub_bridge(&[r#"[{"success": {"/lights/1/state/on": true}}]"#]);
+    assert!(bridge.get_light(0).is_err());
+    assert!(bridge.set_light_state("3a", &LightCommand::default().on()).is_err());
+    assert!(bridge.toggle_group("01").is_err());
+    assert!(bridge.delete_scene("a/b").is_err());
+    assert!(bridge.start_brightness_ramp(0, 10, Duration::from_millis(100)).is_err());
+    assert_eq!(bridge.transport.requests(), vec![]);
+}
+
+#[test]
+fn malformed_response_keeps_body() {
+    match stub_bridge(&["<html>502 Bad Gateway</html>"]).get_light(1) {
+        Err(HueError(HueErrorKind::DeserializeFailed { ref body }, _)) => assert_eq!(body, "<html>502 Bad Gateway</html>"),
+        r => panic!("expected failed deserialization, got {:?}", r),
+    }
+}
+
+#[test]
+fn request_and_response_loggers() {
+    let mut bridge = stub_bridge(&[r#"[{"success": {"/lights/1/state/on": true}}]"#]);
+    let logged = Arc::new(Mutex::new(Vec::new()));
+    let requests = logged.clone();
+    bridge.set_request_logger(Box::new(move |method: &str, url: &str, body: &[u8]| {
+        requests.lock().unwrap().push(format!("{} {} {}", method, url, String::from_utf8_lossy(body)));
+    }));
+    let responses = logged.clone();
+    bridge.set_response_logger(Box::new(move |url: &str, body: &[u8]| {
+        responses.lock().unwrap().push(format!("{} {}", url, String::from_utf8_lossy(body)));
+    }));
+
+    bridge.set_light_state(1, &LightCommand::default().on()).unwrap();
+    assert_eq!(*logged.lock().unwrap(), vec![
+        r#"PUT http://192.168.2.23/api/hello/lights/1/state {"on":true}"#,
+        r#"http://192.168.2.23/api/hello/lights/1/state [{"success": {"/lights/1/state/on": true}}]"#,
+    ]);
+    assert_eq!(bridge.transport.requests().len(), 1);
+}
+
+#[test]
+fn metrics_hook_times_requests() {
+    struct SlowTransport(&'static str);
+
+    impl Transport for SlowTransport {
+        fn request(&self, _: &str, _: &str, _: &BTreeMap<String, String>, _: &[u8]) -> Result<Vec<u8>> {
+            thread::sleep(Duration::from_millis(20));
+            Ok(self.0.as_bytes().to_vec())
+        }
+    }
+
+    let observed = Arc::new(Mutex::new(Vec::new()));
+    let mut bridge = Bridge::with_transport(SlowTransport(r#"[{"success": {"/lights/1/state/on": true}}]"#),
+                                            "192.168.2.23", "hello");
+    let metrics = observed.clone();
+    bridge.set_metrics_hook(Box::new(move |url: &str, elapsed, result: &::std::result::Result<(), ()>| {
+        metrics.lock().unwrap().push((url.to_owned(), elapsed, result.is_ok()));
+    }));
+
+    bridge.set_light_state(1, &LightCommand::default().on()).unwrap();
+    assert!(bridge.get_light(1).is_err());
+
+    let observed = observed.lock().unwrap();
+    assert_eq!(observed.len(), 2);
+    assert_eq!(observed[0].0, "http://192.168.2.23/api/hello/lights/1/state");
+    assert!(observed[0].1 >= Duration::from_millis(20));
+    assert!(observed[0].2);
+    assert_eq!(observed[1].0, "http://192.168.2.23/api/hello/lights/1");
+    assert!(!observed[1].2);
+}
+
+#[test]
+fn shared_between_threads() {
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Bridge>();
+
+    let bridge = stub_bridge(&[r#"[{"success": {"/lights/1/state/on": true}}]"#]);
+    let handles: Vec<_> = (0..4)
+        .map(|_| {
+            let bridge = bridge.clone();
+            thread::spawn(move || bridge.set_light_state(1, &LightCommand::default().on()))
+        })
+        .collect();
+    for handle in handles {
+        handle.join().unwrap().unwrap();
+    }
+    assert_eq!(bridge.transport.requests().len(), 4);
+}
+
+impl HyperTransport {
+    /// Creates a transport with a new hyper `Client`
+    pub fn new() -> Self {
+        HyperTransport::with_client(Client::new())
+    }
+    /// Creates a transport sending requests with `client`
+    pub fn with_client(client: Client<HttpConnector>) -> Self {
+        HyperTransport { client, runtime: Mutex::new(None) }
+    }
+
+    /// The executor of the runtime driving the requests, starting the runtime if needed
+    ///
+    /// The runtime is kept, so connections the client keeps alive are driven by later requests.
+    fn executor(&self) -> Result<TaskExecutor> {
+        let mut runtime = self.runtime.lock().unwrap();
+        if runtime.is_none() {
+            *runtime = Some(Builder::new().core_threads(1).build()?);
+        }
+        Ok(runtime.as_ref().unwrap().executor())
+    }
+}
+
+impl Default for HyperTransport {
+    fn default() -> Self {
+        HyperTransport::new()
+    }
+}
+
+impl Transport for HyperTransport {
+    fn request(&self, method: &str, url: &str, headers: &BTreeMap<String, String>, body: &[u8]) -> Result<Vec<u8>> {
+        let mut request = Request::builder();
+        request.method(method).uri(url);
+        for (name, value) in headers {
+            request.header(&**name, &**value);
+        }
+        let request = request.body(if body.is_empty() { Body::empty() } else { Body::from(body.to_vec()) })?;
+        let response = self.client.request(request)
+            .and_then(|response| response.into_body().concat2());
+
+        // Requests from several threads share the runtime, each waiting only for its own response
+        let (sender, receiver) = mpsc::channel();
+        self.executor()?.spawn(response.then(move |result| {
+            let _ = sender.send(result.map(|body| body.to_vec()));
+            Ok(())
+        }));
+        receiver.recv()
+            .map_err(|_| HueError::from("The runtime stopped before the response arrived"))?
+            .map_err(|e| classify_network_error(e.into()))
+    }
+}
+
+impl<T: Transport> Bridge<T> {
+    fn send_with_body<R: DeserializeOwned>(&self, method: Method, url: &str, body: &[u8]) -> Result<R> {
+        self.call(method.as_str(), url, body)
+    }
+
+    fn send<R: DeserializeOwned>(&self, method: Method, url: &str) -> Result<R> {
+        self.call(method.as_str(), url, &[])
+    }
+
+    /// The command with `on` set if auto-on is enabled and it changes how lit lights look
+    /// without saying whether to turn them on
+    fn with_auto_on(&self, command: &LightCommand) -> LightCommand {
+        let mut command = command.clone();
+        let visible = command.bri.is_some() || command.hue.is_some() || command.sat.is_some() ||
+            command.xy.is_some() || command.ct.is_some() || command.effect.is_some() ||
+            command.bri_inc.is_some() || command.sat_inc.is_some() || command.hue_inc.is_some() ||
+            command.ct_inc.is_some() || command.xy_inc.is_some();
+        if self.auto_on && visible && command.on.is_none() {
+            command.on = Some(true);
+        }
+        command
+    }
+}
+
+#[test]
+fn try_new() {
+    assert_eq!(Bridge::try_new("192.168.2.23", "hello").unwrap().get_ip(), "192.168.2.23");
+    assert_eq!(Bridge::try_new("philips-hue.local", "hello").unwrap().get_ip(), "philips-hue.local");
+    assert_eq!(Bridge::try_new("[fe80::1]:80", "hello").unwrap().get_ip(), "[fe80::1]:80");
+    assert!(Bridge::try_new("192.168.2.23/api/", "hello").is_err());
+    assert!(Bridge::try_new("fe80::1", "hello").is_err());
+}
+
+#[test]
+fn get_ip_and_username() {
+    let b = Bridge::new("test", "hello");
+    assert_eq!(b.get_ip(), "test");
+    assert_eq!(b.get_username(), "hello");
+}
+#[test]
+fn as_other_user() {
+    let b = stub_bridge(&[r#"[{"success": {"/lights/1/state/on": true}}]"#]);
+    let other = b.as_user("other");
+    assert_eq!(other.get_ip(), "192.168.2.23");
+    assert_eq!(other.get_username(), "other");
+    other.set_light_state(1, &LightCommand::default().on()).unwrap();
+    assert_eq!(b.transport.requests()[0].url, "http://192.168.2.23/api/other/lights/1/state");
+}
+
+#[test]
+fn user_agent_and_headers() {
+    let (address, requests) = serve_once("{}");
+    let mut bridge = Bridge::with_user_agent(&*address, "hello", "my_hue_app/1.0");
+    bridge.set_header("Proxy-Authorization", "Basic aGVsbG86d29ybGQ=");
+    bridge.get_all_lights().unwrap();
+    let request = requests.recv().unwrap().to_lowercase();
+    assert!(request.contains("\r\nuser-agent: my_hue_app/1.0\r\n"));
+    assert!(request.contains("\r\nproxy-authorization: basic agvsbg86d29ybgq=\r\n"));
+
+    let (address, requests) = serve_once("{}");
+    Bridge::new(&*address, "hello").get_all_lights().unwrap();
+    assert!(!requests.recv().unwrap().to_lowercase().contains("user-agent"));
+}
+#[test]
+fn connection_errors() {
+    let closed = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    match Bridge::new(closed, "hello").get_all_lights() {
+        Err(ref e @ HueError(HueErrorKind::Connection, _)) => assert!(e.is_connection_error()),
+        r => panic!("expected connection error, got {:?}", r),
+    }
+}
+
+#[test]
+fn hyper_transport() {
+    let (address, requests) = serve_once(r#"[{"success": {"/lights/1/state/on": true}}]"#);
+    let bridge = Bridge::new(&*address, "hello");
+    let successes = bridge.set_light_state(1, &LightCommand::default().on()).unwrap();
+    assert_eq!(successes[0]["/lights/1/state/on"], JsonValue::Bool(true));
+    let request = requests.recv().unwrap();
+    assert!(request.starts_with("PUT /api/hello/lights/1/state HTTP/1.1\r\n"));
+    assert!(request.ends_with(r#"{"on":true}"#));
+}
+
+#[test]
+fn from_discovery() {
+    let discovery = Discovery { id: "001788fffe100491".to_owned(), internalipaddress: "192.168.2.23".to_owned(), port: None };
+    let b = Bridge::from_discovery(&discovery, "hello");
+    assert_eq!(b.get_ip(), "192.168.2.23");
+    assert_eq!(b.get_username(), "hello");
+    assert_eq!(b.bridge_id(), Some("001788fffe100491"));
+    assert_eq!(b.as_user("other").bridge_id(), Some("001788fffe100491"));
+    assert_eq!(Bridge::new("192.168.2.23", "hello").bridge_id(), None);
+
+    let (address, requests) = serve_once("{}");
+    let discovery = Discovery { id: "emulated".to_owned(), internalipaddress: "127.0.0.1".to_owned(), port: port_of(&address) };
+    let b = Bridge::from_discovery(&discovery, "hello");
+    assert_eq!(b.get_ip(), address);
+    assert!(b.get_all_lights().unwrap().is_empty());
+    assert!(requests.recv().unwrap().starts_with("GET /api/hello/lights HTTP/1.1\r\n"));
+}
+
+#[test]
+fn pool_size_keeps_settings() {
+    let (address, requests) = serve_once("{}");
+    let mut bridge = Bridge::new(&*address, "hello");
+    bridge.set_header("Proxy-Authorization", "Basic aGVsbG86d29ybGQ=");
+    let bridge = bridge.with_pool_size(0);
+    assert!(bridge.get_all_lights().unwrap().is_empty());
+    let request = requests.recv().unwrap().to_lowercase();
+    assert!(request.starts_with("get /api/hello/lights http/1.1\r\n"));
+    assert!(request.contains("\r\nproxy-authorization: basic agvsbg86d29ybgq=\r\n"));
+}
+
+#[test]
+fn with_client() {
+    let (address, requests) = serve_once("{}");
+    let client = Client::builder().keep_alive(false).build_http();
+    let bridge = Bridge::with_client(client, &*address, "hello");
+    assert_eq!(bridge.get_ip(), address);
+    assert_eq!(bridge.get_username(), "hello");
+    assert!(bridge.get_all_lights().unwrap().is_empty());
+    assert!(requests.recv().unwrap().starts_with("GET /api/hello/lights HTTP/1.1\r\n"));
+}
+
+#[test]
+#[ignore]
+/// Needs a bridge, e.g. `HUE_IP=192.168.2.23 HUE_USERNAME=... cargo test -- --ignored reused_connections`
+fn reused_connections() {
+    use std::env;
+    use std::time::Instant;
+
+    let (ip, username) = (env::var("HUE_IP").unwrap(), env::var("HUE_USERNAME").unwrap());
+    let time = |bridge: Bridge| {
+        let start = Instant::now();
+        for _ in 0..20 {
+            bridge.get_all_lights().unwrap();
+        }
+        start.elapsed()
+    };
+    let fresh = time(Bridge::new(&*ip, &*username).with_pool_size(0));
+    let reused = time(Bridge::new(&*ip, &*username).with_pool_size(1));
+    println!("20 requests without keep-alive: {:?}, with keep-alive: {:?}", fresh, reused);
+    assert!(reused < fresh);
+}
+
+/// Many commands on the bridge return an array of things that were succesful.
+/// This is a type alias for that type.
+pub type SuccessVec = Vec<JsonMap<String, JsonValue>>;
+
+fn extract<'a, T: Deserialize<'a>>(responses: Vec<HueResponse<T>>) -> Result<Vec<T>> {
+    collect_results(responses)
+}
+
+fn first_delete(deleted: Vec<String>) -> Result<Delete> {
+    deleted.first()
+        .ok_or_else(|| "Malformed response".into())
+        .and_then(|s| s.parse())
+}
+
+impl Bridge<HyperTransport> {
+    /// Creates a `Bridge` on the given IP with the given username
+    pub fn new<S: Into<String>, U: Into<String>>(ip: S, username: U) -> Self {
+        Bridge::with_transport(HyperTransport::new(), ip, username)
+    }
+    /// Creates a `Bridge` on the given IP with the given username, sending requests with `client`
+    ///
+    /// Allows configuring the client as needed, e.g. with a different connector or timeouts.
+    pub fn with_client<S: Into<String>, U: Into<String>>(client: Client<HttpConnector>, ip: S, username: U) -> Self {
+        Bridge::with_transport(HyperTransport::with_client(client), ip, username)
+    }
+    /// Creates a `Bridge` like `new()`, but fails if `ip` isn't an IP address or hostname
+    ///
+    /// Catches mistakes in the address right away instead of at the first request.
+    /// IPv6 addresses have to be written in brackets, e.g. `[fe80::1]`.
+    pub fn try_new<S: Into<String>, U: Into<String>>(ip: S, username: U) -> Result<Self> {
+        let ip = ip.into();
+        if is_valid_address(&ip) {
+            Ok(Bridge::new(ip, username))
+        } else {
+            Err(format!("Invalid bridge address: {:?}", ip).into())
+        }
+    }
+    /// Creates a `Bridge` for a bridge found with `discover()`, using its port if it has one
+    ///
+    /// The id of the bridge is kept, so it can be found again with `discover_by_id()` if its IP changes.
+    pub fn from_discovery<U: Into<String>>(discovery: &Discovery, username: U) -> Self {
+        Bridge {
+            bridge_id: Some(discovery.id().to_owned()),
+            ..Bridge::new(discovery.address(), username)
+        }
+    }
+    /// Creates a `Bridge` from credentials saved with `credentials()`
+    pub fn from_credentials(credentials: &BridgeCredentials) -> Self {
+        Bridge::new(&*credentials.ip, &*credentials.username)
+    }
+    /// Creates a `Bridge` that identifies itself with the given User-Agent in every request
+    pub fn with_user_agent<S: Into<String>, U: Into<String>>(ip: S, username: U, user_agent: &str) -> Self {
+        let mut bridge = Bridge::new(ip, username);
+        bridge.set_user_agent(user_agent);
+        bridge
+    }
+    /// Keeps up to `size` idle connections to the bridge open to reuse for later requests
+    ///
+    /// A size of 0 turns keep-alive off, so every request opens a new connection.
+    pub fn with_pool_size(self, size: usize) -> Self {
+        let client = Client::builder()
+            .keep_alive(size > 0)
+            .max_idle_per_host(size)
+            .build_http();
+        Bridge { transport: Arc::new(HyperTransport::with_client(client)), ..self }
+    }
+}
+
+impl<T: Transport> Bridge<T> {
+    /// Sets a function that gets to inspect every request before it is sent
+    ///
+    /// Useful for finding out which part of a command the bridge rejects.
+    pub fn set_request_logger(&mut self, logger: RequestLogger) {
+        self.request_logger = Some(logger.into());
+    }
+    /// Sets a function that gets to inspect the raw body of every response from the bridge
+    pub fn set_response_logger(&mut self, logger: ResponseLogger) {
+        self.response_logger = Some(logger.into());
+    }
+    /// Makes `set_light_state()` and `set_group_state()` turn the lights on when setting
+    /// their colour or brightness
+    ///
+    /// Off lights don't change visibly when only their colour or brightness is set, which is
+    /// confusing at first. With this enabled, `on: true` is added to commands that set any of
+    /// `bri`, `hue`, `sat`, `xy`, `ct`, `effect` or one of the increments but leave `on` out.
+    /// Commands that set `on` themselves are sent as they are. Disabled by default.
+    pub fn set_auto_on(&mut self, auto_on: bool) {
+        self.auto_on = auto_on;
+    }
+    /// Sets a function that is told how long every request took and whether it succeeded
+    ///
+    /// Requests aren't timed unless a hook is set.
+    pub fn set_metrics_hook(&mut self, hook: MetricsHook) {
+        self.metrics_hook = Some(hook.into());
+    }
+    /// Returns the IP and username of this `Bridge`, to be saved for connecting again later
+    pub fn credentials(&self) -> BridgeCredentials {
+        BridgeCredentials {
+            ip: self.get_ip().to_owned(),
+            username: self.get_username().to_owned(),
+        }
+    }
+    /// Limits the requests changing something on the bridge to `per_second` every second
+    ///
+    /// The bridge can only handle about 10 light commands or 1 group command per second and
+    /// ignores commands beyond that. Requests only reading from the bridge aren't limited.
+    /// Requests are spaced evenly, use `with_rate_limit_and_burst()` to allow short bursts.
+    pub fn with_rate_limit(self, per_second: u32) -> Self {
+        self.with_rate_limit_and_burst(per_second, 1)
+    }
+    /// Limits the requests changing something on the bridge like `with_rate_limit()`, but
+    /// lets up to `burst` of them through at once after a quiet period
+    pub fn with_rate_limit_and_burst(self, per_second: u32, burst: u32) -> Self {
+        Bridge {
+            rate_limiter: Some(Arc::new(RateLimiter::new(per_second, burst))),
+            ..self
+        }
+    }
+    /// Sets the User-Agent sent with every request
+    pub fn set_user_agent(&mut self, user_agent: &str) {
+        self.set_header("User-Agent", user_agent);
+    }
+    /// Sets a header sent with every request, e.g. for authenticating with a proxy
+    pub fn set_header(&mut self, name: &str, value: &str) {
+        self.headers.insert(name.to_owned(), value.to_owned());
+    }
+    /// Returns a connection to the same bridge using another username
+    ///
+    /// The transport, the headers and the loggers are shared with this `Bridge`.
+    pub fn as_user(&self, username: &str) -> Bridge<T> {
+        Bridge {
+            url: format!("http://{}/api/{}/", self.get_ip(), username),
+            ..self.clone()
+        }
+    }
+    /// Gets the IP of bridge
+    ///
+    /// This includes the port if the bridge isn't on the standard one.
+    pub fn get_ip(&self) -> &str {
+        self.url.split('/').nth(2).unwrap()
+    }
+    /// Gets the id of the bridge, if this `Bridge` was created with `from_discovery()`
+    pub fn bridge_id(&self) -> Option<&str> {
+        self.bridge_id.as_deref()
+    }
+    /// Gets the username this `Bridge` uses
+    pub fn get_username(&self) -> &str {
+        self.url.split('/').nth(4).unwrap()
+    }
+    /// Gets all lights that are connected to the bridge
+    pub fn get_all_lights(&self) -> Result<BTreeMap<usize, Light>> {
+        self.send(Method::GET, &format!("{}lights", self.url))
+    }
+    /// Gets all lights ordered numerically by their ID
+    pub fn get_all_lights_sorted(&self) -> Result<Vec<(usize, Light)>> {
+        self.get_all_lights().map(|l| l.into_iter().collect())
+    }
+    /// Starts a thread polling the lights every `interval`, sending every change of their state
+    ///
+    /// The bridge can't notify about changes, so this is the only way to follow them.
+    /// Lights that are added or removed don't cause changes, and failed polls are skipped.
+    /// The thread polls through a clone of this `Bridge` until the returned `LightWatch` is
+    /// stopped or dropped, which it notices right away instead of after the next poll.
+    pub fn watch_lights(&self, interval: Duration) -> LightWatch
+    where T: 'static {
+        let (sender, changes) = mpsc::channel();
+        let (stop, stopped) = mpsc::channel::<()>();
+        let bridge = self.clone();
+        let handle = thread::spawn(move || {
+            let mut last = None;
+            loop {
+                if let Ok(lights) = bridge.get_all_lights() {
+                    for change in last.as_ref().map(|last| light_changes(last, &lights)).unwrap_or_default() {
+                        if sender.send(change).is_err() {
+                            return;
+                        }
+                    }
+                    last = Some(lights);
+                }
+                // Nothing is ever sent, so this only ends early once the handle is gone
+                if stopped.recv_timeout(interval) != Err(RecvTimeoutError::Timeout) {
+                    return;
+                }
+            }
+        });
+        LightWatch { changes, stop, handle }
+    }
+    /// Finds a light by its name, ignoring case
+    ///
+    /// Names aren't guaranteed to be unique, if several lights have the name the one
+    /// with the lowest ID is returned.
+    pub fn find_light_by_name(&self, name: &str) -> Result<Option<(usize, Light)>> {
+        let name = name.to_lowercase();
+        self.get_all_lights().map(|lights| lights.into_iter().find(|(_, l)| l.name.to_lowercase() == name))
+    }
+    /// Counts the lights connected to the bridge
+    ///
+    /// The bridge has no way of counting, so this fetches all lights like `get_all_lights()`.
+    pub fn count_lights(&self) -> Result<usize> {
+        self.get_all_lights().map(|l| l.len())
+    }
+    /// Gets the light with the specific id
+    pub fn get_light(&self, id: impl IntoId<LightId>) -> Result<Light> {
+        let id = id.into_id()?;
+        self.send(Method::GET, &format!("{}lights/{}", self.url, id))
+    }
+    /// Gets the light with the specific id, or `None` if there is no such light
+    pub fn try_get_light(&self, id: impl IntoId<LightId>) -> Result<Option<Light>> {
+        let id = id.into_id()?;
+        not_found_as_none(self.get_light(id))
+    }
+    /// Gets all the light that were found last time a search for new lights was done
+    pub fn get_new_lights(&self) -> Result<BTreeMap<usize, Light>> {
+        // TODO return lastscan too
+        self.send(Method::GET, &format!("{}lights/new", self.url))
+    }
+    /// Gets when the bridge last searched for new lights, or whether it's searching right now
+    pub fn get_last_scan(&self) -> Result<ScanState> {
+        let mut new: JsonMap<String, JsonValue> = self.send(Method::GET, &format!("{}lights/new", self.url))?;
+        let lastscan = new.remove("lastscan").ok_or_else(|| HueError::from("Malformed response"))?;
+        from_value(lastscan).map_err(From::from)
+    }
+    /// Makes the bridge search for new lights (and switches).
+    ///
+    /// The found lights can be retrieved with `get_new_lights()`
+    pub fn search_for_new_lights(&self) -> Result<SuccessVec> {
+        // TODO Allow deviceids to be specified
+        self.send(Method::POST, &format!("{}lights", self.url)).and_then(extract)
+    }
+    /// Sets the state of a light by sending a `LightCommand` to the bridge for this light
+    ///
+    /// Fails with `EmptyCommand` without sending anything if the command is empty.
+    pub fn set_light_state(&self, id: impl IntoId<LightId>, command: &LightCommand) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        if command.is_empty() {
+            return Err(HueErrorKind::EmptyCommand.into());
+        }
+        self.send_with_body(Method::PUT, &format!("{}lights/{}/state", self.url, id),
+                            &to_vec(&self.with_auto_on(command))?)
+            .and_then(extract)
+    }
+    /// Sets the state of a light like `set_light_state()`, returning the successes and errors
+    /// of each part of the command instead of failing when any part fails
+    pub fn set_light_state_detailed(&self, id: impl IntoId<LightId>, command: &LightCommand) -> Result<BridgeResponse> {
+        let id = id.into_id()?;
+        if command.is_empty() {
+            return Err(HueErrorKind::EmptyCommand.into());
+        }
+        self.send_with_body(Method::PUT, &format!("{}lights/{}/state", self.url, id),
+                            &to_vec(&self.with_auto_on(command))?)
+            .map(BridgeResponse::from_responses)
+    }
+    /// Turns the light off if it is on and on if it is off
+    ///
+    /// The light is fetched first, so anything switching it in between the two requests
+    /// makes this switch it to the state it already has.
+    pub fn toggle_light(&self, id: impl IntoId<LightId>) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        let command = if self.get_light(id)?.state.on { LightCommand::default().off() } else { LightCommand::default().on() };
+        self.set_light_state(id, &command)
+    }
+    /// Sets the state of a light, unless the bridge reports it as unreachable
+    ///
+    /// The bridge accepts commands for unreachable lights without them ever changing, so this
+    /// fetches the light first and fails with `BridgeError::DeviceIsUnreachable` instead.
+    pub fn set_light_state_checked(&self, id: impl IntoId<LightId>, command: &LightCommand) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        if !self.get_light(id)?.state.reachable {
+            return Err(HueErrorKind::BridgeError {
+                address: format!("/lights/{}/state", id),
+                description: format!("Device, light {}, is not reachable", id),
+                error: BridgeError::DeviceIsUnreachable,
+            }.into());
+        }
+        self.set_light_state(id, command)
+    }
+    /// Sets the state of a light and checks that the light is in that state afterwards
+    ///
+    /// The bridge doesn't guarantee a command reaches the light, so the command is resent
+    /// up to `retries` times as long as the light reports a different state.
+    /// Transient errors from the bridge, see `BridgeError::is_transient()`, are retried as well.
+    /// The last state read from the light is returned, whether the command took effect or not.
+    pub fn set_light_state_confirmed(&self, id: impl IntoId<LightId>, command: &LightCommand, retries: usize) -> Result<LightState> {
+        let id = id.into_id()?;
+        let mut attempts = 0;
+        loop {
+            match self.set_light_state(id, command) {
+                Err(HueError(HueErrorKind::BridgeError{error, ..}, _)) if error.is_transient() && attempts < retries => {
+                    attempts += 1;
+                    continue;
+                }
+                result => result?,
+            };
+            let state = self.get_light(id)?.state;
+            if attempts == retries || command.took_effect(&state) {
+                return Ok(state);
+            }
+            attempts += 1;
+        }
+    }
+    /// Sets the light to an sRGB colour, fetching the light first to find out its gamut
+    pub fn set_light_color(&self, id: impl IntoId<LightId>, rgb: (u8, u8, u8)) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        let light = self.get_light(id)?;
+        self.set_light_color_for(id, &light, rgb)
+    }
+    /// Sets the light to an sRGB colour, moved into the gamut of the given light
+    ///
+    /// The gamut the light reports is used if it has one. Other lights of unknown models get
+    /// colours in gamut C, the one of current Hue lights.
+    pub fn set_light_color_for(&self, id: impl IntoId<LightId>, light: &Light, rgb: (u8, u8, u8)) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        let gamut = light.gamut().unwrap_or(color::GAMUT_C);
+        self.set_light_state(id, &LightCommand::default().with_xy(color::rgb_to_xy_in_gamut(rgb, gamut)))
+    }
+    /// Makes the light breathe once so it can be located physically
+    ///
+    /// Only the `alert` is sent, so this also works on lights that are off.
+    pub fn identify_light(&self, id: impl IntoId<LightId>) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        self.set_light_state(id, &LightCommand::default().with_alert(Alert::Select))
+    }
+    /// Starts a thread repeatedly incrementing the brightness of the light by `delta_per_step`
+    ///
+    /// Stops by itself when the light reaches its maximum or minimum brightness, otherwise
+    /// call `stop()` on the returned handle, e.g. when a dimmer button is released.
+    /// The thread sends through a clone of this `Bridge`, so its headers, loggers and rate
+    /// limit apply to the ramp as well.
+    /// Fails without starting a thread if the ID is invalid.
+    pub fn start_brightness_ramp(&self, id: impl IntoId<LightId>, delta_per_step: i16, interval: Duration)
+                                 -> Result<BrightnessRamp>
+    where T: 'static {
+        let id = id.into_id()?;
+        let stop = Arc::new(AtomicBool::new(false));
+        let bridge = self.clone();
+        let thread_stop = stop.clone();
+        let handle = thread::spawn(move || {
+            run_ramp(delta_per_step, interval, &thread_stop, |delta| {
+                bridge.set_light_state(id, &LightCommand::default().with_bri_inc(delta))
+            })
+        });
+        Ok(BrightnessRamp { stop, handle })
+    }
+    /// Renames the light
+    pub fn rename_light(&self, id: impl IntoId<LightId>, name: String) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        let mut name_map = BTreeMap::new();
+        name_map.insert("name".to_owned(), name);
+        self.send_with_body(Method::PUT, &format!("{}lights/{}", self.url, id),
+                            &to_vec(&name_map)?)
+            .and_then(extract)
+    }
+    /// Renames every light in the map, returning the result for each light
+    ///
+    /// A light that fails to be renamed doesn't stop the others. If a rate limit is set with
+    /// `with_rate_limit()` the renames are spaced accordingly.
+    pub fn rename_lights(&self, names: &BTreeMap<usize, String>) -> Vec<(usize, Result<SuccessVec>)> {
+        names.iter().map(|(&id, name)| (id, self.rename_light(id, name.clone()))).collect()
+    }
+    /// Sets the archetype, function and direction of the light, e.g. to pick its icon in apps
+    pub fn set_light_config(&self, id: impl IntoId<LightId>, modifier: &LightConfigModifier) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        let mut config_map = BTreeMap::new();
+        config_map.insert("config", modifier);
+        self.send_with_body(Method::PUT, &format!("{}lights/{}", self.url, id),
+                            &to_vec(&config_map)?)
+            .and_then(extract)
+    }
+    /// Deletes a light from the bridge
+    pub fn delete_light(&self, id: impl IntoId<LightId>) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        self.send(Method::DELETE, &format!("{}lights/{}", self.url, id)).and_then(extract)
+    }
+    /// Deletes a light from the bridge, returning what the bridge reported as deleted
+    pub fn delete_light_typed(&self, id: impl IntoId<LightId>) -> Result<Delete> {
+        let id = id.into_id()?;
+        self.send(Method::DELETE, &format!("{}lights/{}", self.url, id))
+            .and_then(extract)
+            .and_then(first_delete)
+    }
+
+    // GROUPS
+
+    /// Gets all groups of the bridge
+    pub fn get_all_groups(&self) -> Result<BTreeMap<usize, Group>> {
+        self.send(Method::GET, &format!("{}groups", self.url))
+    }
+    /// Gets all groups ordered numerically by their ID
+    pub fn get_all_groups_sorted(&self) -> Result<Vec<(usize, Group)>> {
+        self.get_all_groups().map(|g| g.into_iter().collect())
+    }
+    /// Gets all groups that are rooms, see `Group::room_class()` for their classes
+    pub fn get_rooms(&self) -> Result<BTreeMap<usize, Group>> {
+        self.get_all_groups().map(|g| g.into_iter().filter(|(_, group)| group.group_type == GroupType::Room).collect())
+    }
+    /// Finds a group by its name, ignoring case
+    ///
+    /// Names aren't guaranteed to be unique, if several groups have the name the one
+    /// with the lowest ID is returned.
+    pub fn find_group_by_name(&self, name: &str) -> Result<Option<(usize, Group)>> {
+        let name = name.to_lowercase();
+        self.get_all_groups().map(|groups| groups.into_iter().find(|(_, g)| g.name.to_lowercase() == name))
+    }
+    /// Counts the groups of the bridge
+    ///
+    /// The bridge has no way of counting, so this fetches all groups like `get_all_groups()`.
+    pub fn count_groups(&self) -> Result<usize> {
+        self.get_all_groups().map(|g| g.len())
+    }
+    /// Creates a group and returns the ID of the group
+    pub fn create_group(&self, name: String, lights: Vec<usize>, group_type: GroupType, room_class: Option<RoomClass>) -> Result<usize> {
+        self.create_group_from(&GroupCreator {
+            name,
+            lights,
+            group_type,
+            class: room_class,
+            recycle: None,
+        })
+    }
+    /// Creates a group and returns the ID of the group
+    ///
+    /// Unlike `create_group()` this allows marking the group as `recycle`, so the bridge
+    /// cleans it up by itself.
+    pub fn create_group_from(&self, group: &GroupCreator) -> Result<usize> {
+        let r: Id<String> = self.send_with_body(Method::POST, &format!("{}groups", self.url), &to_vec(group)?)?;
+        r.id.parse().map_err(|_| format!("Malformed group id {:?}", r.id).into())
+    }
+    /// Creates a group and returns its ID together with the group as the bridge stored it
+    ///
+    /// The bridge only responds with the ID, so the group is fetched afterwards.
+    pub fn create_group_full(&self, group: &GroupCreator) -> Result<(usize, Group)> {
+        let id = self.create_group_from(group)?;
+        self.get_group_attributes(id).map(|created| (id, created))
+    }
+    /// Gets extra information about a specific group
+    pub fn get_group_attributes(&self, id: impl IntoId<GroupId>) -> Result<Group> {
+        let id = id.into_id()?;
+        self.send(Method::GET, &format!("{}groups/{}", self.url, id))
+    }
+    /// Gets extra information about a specific group, or `None` if there is no such group
+    pub fn try_get_group_attributes(&self, id: impl IntoId<GroupId>) -> Result<Option<Group>> {
+        let id = id.into_id()?;
+        not_found_as_none(self.get_group_attributes(id))
+    }
+    /// Gets only the state of a group, e.g. to poll whether any of its lights are on
+    ///
+    /// The bridge has no endpoint for this, so the whole group is fetched. Groups that
+    /// don't report a state, like on old bridges, give `None`.
+    pub fn get_group_state(&self, id: impl IntoId<GroupId>) -> Result<Option<GroupState>> {
+        let id = id.into_id()?;
+        self.get_group_attributes(id).map(|g| g.state)
+    }
+    /// Set the name, light and class of a group
+    pub fn set_group_attributes(&self, id: impl IntoId<GroupId>, attr: &GroupCommand) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        self.send_with_body(Method::PUT, &format!("{}groups/{}", self.url, id),
+                            &to_vec(attr)?)
+            .and_then(extract)
+    }
+    /// Renames the group
+    pub fn rename_group(&self, id: impl IntoId<GroupId>, name: String) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        let mut name_map = BTreeMap::new();
+        name_map.insert("name".to_owned(), name);
+        self.send_with_body(Method::PUT, &format!("{}groups/{}", self.url, id),
+                            &to_vec(&name_map)?)
+            .and_then(extract)
+    }
+    /// Changes the class of a room without touching its name or lights
+    pub fn set_group_class(&self, id: impl IntoId<GroupId>, class: RoomClass) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        let mut class_map = BTreeMap::new();
+        class_map.insert("class", class);
+        self.send_with_body(Method::PUT, &format!("{}groups/{}", self.url, id),
+                            &to_vec(&class_map)?)
+            .and_then(extract)
+    }
+    /// Sets the state of all lights in the group.
+    ///
+    /// `ALL_LIGHTS_GROUP` is a special group containing all lights known to the bridge
+    ///
+    /// Fields left out of the command are left as they are, so a command with only `bri`
+    /// dims the lights of the group that are on without turning on the others, unless
+    /// `set_auto_on()` is enabled. Fails with `EmptyCommand` without sending anything if the
+    /// command is empty.
+    pub fn set_group_state(&self, id: impl IntoId<GroupId>, state: &LightCommand) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        if state.is_empty() {
+            return Err(HueErrorKind::EmptyCommand.into());
+        }
+        self.send_with_body(Method::PUT, &format!("{}groups/{}/action", self.url, id),
+                            &to_vec(&self.with_auto_on(state))?)
+            .and_then(extract)
+    }
+    /// Sets the state of all lights in the group like `set_group_state()`, returning the
+    /// successes and errors of each part of the command instead of failing when any part fails
+    pub fn set_group_state_detailed(&self, id: impl IntoId<GroupId>, state: &LightCommand) -> Result<BridgeResponse> {
+        let id = id.into_id()?;
+        if state.is_empty() {
+            return Err(HueErrorKind::EmptyCommand.into());
+        }
+        self.send_with_body(Method::PUT, &format!("{}groups/{}/action", self.url, id),
+                            &to_vec(&self.with_auto_on(state))?)
+            .map(BridgeResponse::from_responses)
+    }
+    /// Turns all lights of the group off if any of them is on, and on otherwise
+    ///
+    /// Like `toggle_light()` this fetches the group first, which races with anything else
+    /// switching its lights. Groups without a state, like on old bridges, are turned on.
+    pub fn toggle_group(&self, id: impl IntoId<GroupId>) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        let any_on = self.get_group_state(id)?.is_some_and(|s| s.any_on);
+        let command = if any_on { LightCommand::default().off() } else { LightCommand::default().on() };
+        self.set_group_state(id, &command)
+    }
+    /// Sets the state of all lights known to the bridge
+    pub fn set_all_lights_state(&self, state: &LightCommand) -> Result<SuccessVec> {
+        self.set_group_state(ALL_LIGHTS_GROUP, state)
+    }
+    /// Turns on all lights known to the bridge
+    pub fn all_lights_on(&self) -> Result<SuccessVec> {
+        self.set_all_lights_state(&LightCommand::default().on())
+    }
+    /// Turns off all lights known to the bridge
+    pub fn all_lights_off(&self) -> Result<SuccessVec> {
+        self.set_all_lights_state(&LightCommand::default().off())
+    }
+    /// Makes all lights in the group breathe once so they can be located physically
+    pub fn identify_group(&self, id: impl IntoId<GroupId>) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        self.set_group_state(id, &LightCommand::default().with_alert(Alert::Select))
+    }
+    /// Deletes the specified group
+    ///
+    /// It's not allowed to delete groups of type `LightSource` or `Luminaire`.
+    pub fn delete_group(&self, id: impl IntoId<GroupId>) -> Result<Vec<String>> {
+        let id = id.into_id()?;
+        self.send(Method::DELETE, &format!("{}groups/{}", self.url, id)).and_then(extract)
+    }
+    /// Deletes all groups of the given type and returns their IDs
+    ///
+    /// Luminaires and light sources are created by the bridge and can't be deleted, so nothing
+    /// is deleted for these types. Groups the bridge refuses to delete because of their type
+    /// are skipped.
+    pub fn delete_groups_of_type(&self, group_type: GroupType) -> Result<Vec<usize>> {
+        let mut deleted = Vec::new();
+        if matches!(group_type, GroupType::Luminaire | GroupType::LightSource) {
+            return Ok(deleted);
+        }
+        for (id, _) in self.get_all_groups()?.into_iter().filter(|(_, group)| group.group_type == group_type) {
+            match self.delete_group(id) {
+                Ok(_) => deleted.push(id),
+                Err(HueError(HueErrorKind::BridgeError{error: BridgeError::UpdateOrDeleteGroupOfThisTypeNotAllowed, ..}, _)) => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(deleted)
+    }
+    /// Deletes the specified group, returning what the bridge reported as deleted
+    pub fn delete_group_typed(&self, id: impl IntoId<GroupId>) -> Result<Delete> {
+        let id = id.into_id()?;
+        self.delete_group(id).and_then(first_delete)
+    }
+
+    // CONFIGURATION
+
+    /// Returns detailed information about the configuration of the bridge.
+    pub fn get_configuration(&self) -> Result<Configuration> {
+        self.send(Method::GET, &format!("{}config", self.url))
+    }
+    /// Gets the whitelist entry of the username of this `Bridge`, e.g. to show when it was registered
+    ///
+    /// Fails with a `ResourceNotAvailable` error if the user has been removed from the whitelist.
+    pub fn get_current_user(&self) -> Result<WhitelistUser> {
+        let config = self.get_configuration()?;
+        config.whitelist_user(self.get_username())
+            .cloned()
+            .ok_or_else(|| HueErrorKind::BridgeError {
+                address: format!("/config/whitelist/{}", self.get_username()),
+                description: "user not found in whitelist".to_owned(),
+                error: BridgeError::ResourceNotAvailable,
+            }.into())
+    }
+    /// Checks that the username of this `Bridge` is authorized on the bridge
+    ///
+    /// Fails with `HueErrorKind::Unauthorized` if a new user has to be registered.
+    pub fn verify(&self) -> Result<()> {
+        self.send::<BTreeMap<String, JsonValue>>(Method::GET, &format!("{}lights", self.url))
+            .map(|_| ())
+            .map_err(|e| if e.is_unauthorized() {
+                HueError::with_chain(e, HueErrorKind::Unauthorized)
+            } else {
+                e
+            })
+    }
+    /// Checks that the bridge can be reached, without changing anything on it
+    ///
+    /// Fails with `HueErrorKind::Unreachable` if no connection could be made. The bridge
+    /// answers this request even if the username is wrong, so this doesn't check it.
+    pub fn ping(&self) -> Result<()> {
+        self.send::<JsonValue>(Method::GET, &format!("{}config", self.url))
+            .map(|_| ())
+            .map_err(connection_as_unreachable)
+    }
+    /// Gets the Olson IDs of the time zones the bridge accepts as `timezone` in its configuration
+    pub fn get_timezones(&self) -> Result<Vec<String>> {
+        self.send(Method::GET, &format!("{}info/timezones", self.url))
+    }
+    /// Sets some configuration values.
+    pub fn modify_configuration(&self, command: &ConfigurationModifier) -> Result<SuccessVec> {
+        self.send_with_body(Method::PUT, &format!("{}config", self.url),
+                            &to_vec(command)?)
+            .and_then(extract)
+    }
+    /// Makes the bridge use the proxy at the given address and port, or no proxy for `None`
+    pub fn set_proxy(&self, proxy: Option<(String, u16)>) -> Result<SuccessVec> {
+        self.modify_configuration(&ConfigurationModifier::proxy(proxy))
+    }
+    /// Makes the bridge perform a touchlink, stealing nearby lights from other bridges.
+    pub fn touchlink(&self) -> Result<SuccessVec> {
+        self.modify_configuration(&ConfigurationModifier {
+            touchlink: Some(true),
+            ..Default::default()
+        })
+    }
+    /// Lets the bridge search for software updates for itself and its lights.
+    pub fn check_for_update(&self) -> Result<SuccessVec> {
+        self.modify_configuration(&ConfigurationModifier {
+            swupdate: Some(SoftwareUpdateModifier { checkforupdate: true, ..Default::default() }),
+            ..Default::default()
+        })
+    }
+    /// Starts installing the software updates the bridge has downloaded.
+    pub fn start_software_update(&self) -> Result<SuccessVec> {
+        self.modify_configuration(&ConfigurationModifier {
+            swupdate: Some(SoftwareUpdateModifier { updatestate: Some(3), ..Default::default() }),
+            ..Default::default()
+        })
+    }
+    /// Deletes the specified user removing them from the whitelist.
+    pub fn delete_user(&self, username: &str) -> Result<Vec<String>> {
+        self.send(Method::DELETE, &format!("{}config/whitelist/{}", self.url, username))
+            .and_then(extract)
+    }
+    /// Deletes the specified user, returning the user the bridge reported as deleted
+    pub fn delete_user_typed(&self, username: &str) -> Result<UserDeleted> {
+        self.delete_user(username)?
+            .first()
+            .ok_or_else(|| "Malformed response".into())
+            .and_then(|s| s.parse())
+    }
+    /// Fetches the entire datastore from the bridge.
+    ///
+    /// This is a resource intensive command for the bridge, and should therefore be used sparingly.
+    pub fn get_full_state(&self) -> Result<FullState> {
+        self.send(Method::GET, &self.url)
+    }
+    /// Gets the entire datastore of the bridge as pretty printed JSON, e.g. to save as a backup
+    pub fn export_state(&self) -> Result<String> {
+        Ok(::serde_json::to_string_pretty(&self.get_full_state()?)?)
+    }
+
+    /// Gets the same as `get_full_state()` with a separate request for each resource
+    ///
+    /// The requests are sent concurrently, each from its own thread, which spreads the load
+    /// on the bridge. If any of them fails, the error names the resource that failed.
+    pub fn get_state_parallel(&self) -> Result<FullState> {
+        let parts: Vec<_> = thread::scope(|scope| {
+            let handles: Vec<_> = FULL_STATE_PARTS.iter()
+                .map(|&(resource, _)| scope.spawn(move || {
+                    self.send::<JsonValue>(Method::GET, &format!("{}{}", self.url, resource))
+                }))
+                .collect();
+            handles.into_iter().map(|handle| handle.join()).collect()
+        });
+
+        let mut state = JsonMap::new();
+        for (&(resource, field), part) in FULL_STATE_PARTS.iter().zip(parts) {
+            let part = part
+                .unwrap_or_else(|_| Err(format!("fetching {} panicked", resource).into()))
+                .chain_err(|| format!("could not get {}", resource))?;
+            state.insert(field.to_owned(), part);
+        }
+        Ok(from_value(JsonValue::Object(state))?)
+    }
+
+    /// Sets the state of lights in the group to the state in the scene
+    ///
+    /// Note that this will affect that are both in the group and in the scene.
+    /// Using `ALL_LIGHTS_GROUP` will set all the lights in the scene, since it is a special
+    /// group that contains all lights
+    ///
+    /// For the simple case use `SceneRecall::new(scene_id)`.
+    pub fn recall_scene_in_group(&self, group_id: impl IntoId<GroupId>, recall: &SceneRecall) -> Result<SuccessVec> {
+        let group_id = group_id.into_id()?;
+        self.send_with_body(Method::PUT, &format!("{}groups/{}/action", self.url, group_id),
+                            &to_vec(recall)?)
+            .and_then(extract)
+    }
+    /// Same as `recall_scene_in_group()` but overrides the transition time (in multiples of 100ms)
+    /// stored in the scene
+    pub fn recall_scene_in_group_with_transition(&self, group_id: impl IntoId<GroupId>, scene_id: impl IntoId<SceneId>,
+        transitiontime: u16) -> Result<SuccessVec> {
+        let (group_id, scene_id) = (group_id.into_id()?, scene_id.into_id()?);
+        self.recall_scene_in_group(group_id, &SceneRecall::new(&scene_id).with_transitiontime(transitiontime))
+    }
+
+    // RULES
+
+    /// Creates a rule, e.g. built with `RuleBuilder`, and returns its ID
+    pub fn create_rule(&self, rule: &RuleCreator) -> Result<String> {
+        let r: Id<String> = self.send_with_body(Method::POST, &format!("{}rules", self.url), &to_vec(rule)?)?;
+        Ok(r.id)
+    }
+
+    // SCENES
+
+    /// Gets all scenes of the bridge
+    pub fn get_all_scenes(&self) -> Result<BTreeMap<String, Scene>> {
+        self.send(Method::GET, &format!("{}scenes", self.url))
+    }
+    /// Gets all scenes ordered by name, since their IDs are not meaningful
+    ///
+    /// Scenes with the same name are ordered by ID.
+    pub fn get_all_scenes_sorted(&self) -> Result<Vec<(String, Scene)>> {
+        let mut scenes: Vec<_> = self.get_all_scenes()?.into_iter().collect();
+        scenes.sort_by(|a, b| (&a.1.name, &a.0).cmp(&(&b.1.name, &b.0)));
+        Ok(scenes)
+    }
+    /// Counts the scenes of the bridge
+    ///
+    /// The bridge has no way of counting, so this fetches all scenes like `get_all_scenes()`.
+    pub fn count_scenes(&self) -> Result<usize> {
+        self.get_all_scenes().map(|s| s.len())
+    }
+    /// Gets all scenes that belong to the specified group
+    ///
+    /// The bridge can't filter scenes itself, so all scenes are fetched and filtered here.
+    pub fn get_scenes_for_group(&self, group_id: impl IntoId<GroupId>) -> Result<BTreeMap<String, Scene>> {
+        let group_id = group_id.into_id()?;
+        self.get_all_scenes().map(|scenes| scenes
+            .into_iter()
+            .filter(|(_, scene)| scene.group == Some(group_id.into()))
+            .collect()
+        )
+    }
+    /// Creates a scene on the bridge and returns the ID of the created scene.
+    pub fn create_scene(&self, scene: &SceneCreater) -> Result<String> {
+        let r: HueResponse<Id<String>> = self.send_with_body(Method::POST, &format!("{}scenes", self.url),
+                                                             &to_vec(scene)?)?;
+        r.into_result().map(|g| g.id)
+    }
+    /// Creates a scene on the bridge and returns the ID of the created scene together with
+    /// everything the bridge responded with, such as warnings about specific lights.
+    pub fn create_scene_full(&self, scene: &SceneCreater) -> Result<(String, SuccessVec)> {
+        let successes = self.send_with_body(Method::POST, &format!("{}scenes", self.url),
+                                            &to_vec(scene)?)
+            .and_then(extract)?;
+        let id = successes.iter()
+            .filter_map(|s: &JsonMap<String, JsonValue>| s.get("id").and_then(JsonValue::as_str))
+            .next()
+            .ok_or_else(|| HueError::from("Malformed response"))?
+            .to_owned();
+        Ok((id, successes))
+    }
+    /// Sets general things in the specified scene
+    pub fn modify_scene(&self, id: impl IntoId<SceneId>, scene: &SceneModifier) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        self.send_with_body(Method::PUT, &format!("{}scenes/{}", self.url, id), &to_vec(scene)?)
+            .and_then(extract)
+    }
+    /// Renames the scene
+    pub fn rename_scene(&self, id: impl IntoId<SceneId>, name: String) -> Result<SuccessVec> {
+        let id = id.into_id()?;
+        let mut name_map = BTreeMap::new();
+        name_map.insert("name".to_owned(), name);
+        self.send_with_body(Method::PUT, &format!("{}scenes/{}", self.url, id), &to_vec(&name_map)?)
+            .and_then(extract)
+    }
+    /// Sets the light state of the specified ID that is stored in the scene
+    ///
+    /// Use `on: Some(false)` for lights that should be turned off by the scene, leaving `on`
+    /// out means recalling the scene doesn't turn the light on or off.
+    pub fn set_light_state_in_scene(&self, scene_id: impl IntoId<SceneId>, light_id: impl IntoId<LightId>,
+        state: &LightStateChange) -> Result<SuccessVec> {
+        let (scene_id, light_id) = (scene_id.into_id()?, light_id.into_id()?);
+        self.send_with_body(Method::PUT, &format!("{}scenes/{}/lightstates/{}", self.url,
+            scene_id, light_id), &to_vec(state)?).and_then(extract)
+    }
+    /// Deletes the specified scene
+    pub fn delete_scene(&self, id: impl IntoId<SceneId>) -> Result<Vec<String>> {
+        let id = id.into_id()?;
+        self.send(Method::DELETE, &format!("{}scenes/{}", self.url, id)).and_then(extract)
+    }
+    /// Deletes all scenes marked `recycle` and returns their IDs
+    ///
+    /// Scenes that fail to be deleted don't stop the others from being deleted. If any
+    /// failed the errors are returned instead, see `HueErrorKind::Multiple`.
+    pub fn delete_recyclable_scenes(&self) -> Result<Vec<String>> {
+        let mut deleted = Vec::new();
+        let mut errors = Vec::new();
+        for (id, _) in self.get_all_scenes()?.into_iter().filter(|(_, scene)| scene.recycle) {
+            match self.delete_scene(id.as_str()) {
+                Ok(_) => deleted.push(id),
+                Err(e) => errors.push(e),
+            }
+        }
+        if errors.len() > 1 {
+            let codes = errors.iter()
+                .map(|e| match *e.kind() {
+                    HueErrorKind::BridgeError{error, ..} => error,
+                    _ => BridgeError::Other,
+                })
+                .collect();
+            Err(HueErrorKind::Multiple(codes).into())
+        } else if let Some(e) = errors.pop() {
+            Err(e)
+        } else {
+            Ok(deleted)
+        }
+    }
+    /// Deletes the specified scene, returning what the bridge reported as deleted
+    pub fn delete_scene_typed(&self, id: impl IntoId<SceneId>) -> Result<Delete> {
+        let id = id.into_id()?;
+        self.delete_scene(id).and_then(first_delete)
+    }
+    /// Gets the scene with the specified ID with its `lightstates`
+    pub fn get_scene_with_states(&self, id: impl IntoId<SceneId>) -> Result<Scene> {
+        let id = id.into_id()?;
+        self.send(Method::GET, &format!("{}scenes/{}", self.url, id))
+    }
+    /// Gets the scene with the specified ID with its `lightstates`, or `None` if there is no such scene
+    pub fn try_get_scene_with_states(&self, id: impl IntoId<SceneId>) -> Result<Option<Scene>> {
+        let id = id.into_id()?;
+        not_found_as_none(self.get_scene_with_states(id))
+    }
+}
+
+#[test]
+fn identify_light_and_group() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/lights/3/state/alert": "select"}}]"#,
+                               r#"[{"success": {"/groups/2/action/alert": "select"}}]"#]);
+    let identified = bridge.identify_light(3).unwrap();
+    assert_eq!(identified[0]["/lights/3/state/alert"], JsonValue::from("select"));
+    bridge.identify_group(2).unwrap();
+    assert_eq!(bridge.transport.requests(), vec![
+        ("PUT", "http://192.168.2.23/api/hello/lights/3/state", r#"{"alert":"select"}"#).into(),
+        ("PUT", "http://192.168.2.23/api/hello/groups/2/action", r#"{"alert":"select"}"#).into(),
+    ]);
+}
+
+#[test]
+fn touchlink_and_software_updates() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/config/touchlink": true}}]"#]);
+    bridge.touchlink().unwrap();
+    bridge.check_for_update().unwrap();
+    bridge.start_software_update().unwrap();
+    let bodies: Vec<_> = bridge.transport.requests().into_iter().map(|r| r.body).collect();
+    assert_eq!(bodies, vec![r#"{"touchlink":true}"#,
+                            r#"{"swupdate":{"checkforupdate":true}}"#,
+                            r#"{"swupdate":{"updatestate":3}}"#]);
+    assert_eq!(bridge.transport.requests()[0].url, "http://192.168.2.23/api/hello/config");
+}
+
+#[test]
+fn set_and_disable_proxy() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/config/proxyaddress": "192.168.2.1"}}, {"success": {"/config/proxyport": 3128}}]"#]);
+    bridge.set_proxy(Some(("192.168.2.1".to_owned(), 3128))).unwrap();
+    bridge.set_proxy(None).unwrap();
+    assert_eq!(bridge.transport.requests(), vec![
+        ("PUT", "http://192.168.2.23/api/hello/config", r#"{"proxyaddress":"192.168.2.1","proxyport":3128}"#).into(),
+        ("PUT", "http://192.168.2.23/api/hello/config", r#"{"proxyaddress":"none","proxyport":0}"#).into(),
+    ]);
+}
+
+#[test]
+fn scenes_for_group() {
+    let bridge = stub_bridge(&[r#"{
+        "4e1c6b20e-on-0": {"name": "Kathy on 1449133269486", "type": "LightScene", "lights": ["2", "3"],
+                           "owner": "ffffffffe0341b1b376a2389376a2389", "recycle": true, "locked": false,
+                           "appdata": {}, "picture": "", "lastupdated": "2015-12-03T08:57:13"},
+        "ab341ef24": {"name": "Sunset", "type": "GroupScene", "group": "1", "lights": ["1", "2"],
+                      "owner": "ffffffffe0341b1b376a2389376a2389", "recycle": false, "locked": false,
+                      "appdata": {}, "picture": "", "lastupdated": "2015-12-03T10:09:22"},
+        "cd782ba43": {"name": "Reading", "type": "GroupScene", "group": "2", "lights": ["4"],
+                      "owner": "ffffffffe0341b1b376a2389376a2389", "recycle": false, "locked": false,
+                      "appdata": {}, "picture": "", "lastupdated": "2015-12-03T10:09:22"}
+    }"#]);
+    let scenes = bridge.get_scenes_for_group(1).unwrap();
+    assert_eq!(scenes.keys().collect::<Vec<_>>(), vec!["ab341ef24"]);
+    assert_eq!(bridge.transport.requests(), vec![("GET", "http://192.168.2.23/api/hello/scenes", "").into()]);
+}
+
+#[test]
+fn create_scene_with_full_response() {
+    let bridge = stub_bridge(&[r#"[
+        {"success": {"id": "ab341ef24"}},
+        {"success": {"/scenes/ab341ef24/lightstates/3": "light 3 is unreachable, its current state was not stored"}}
+    ]"#, r#"[{"success": {"/scenes/ab341ef24/lightstates/3": "not stored"}}]"#]);
+    let scene = SceneCreater {
+        name: "Sunset".to_owned(),
+        lights: vec![1, 3],
+        recycle: None,
+        appdata: None,
+        picture: None,
+        transitiontime: None,
+        scene_type: None,
+        group: None,
+    };
+    let (id, successes) = bridge.create_scene_full(&scene).unwrap();
+    assert_eq!(id, "ab341ef24");
+    assert_eq!(successes.len(), 2);
+    assert_eq!(bridge.transport.requests()[0].url, "http://192.168.2.23/api/hello/scenes");
+
+    assert!(bridge.create_scene_full(&scene).is_err());
+}
+
+#[test]
+fn rename_group_and_scene() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/groups/2/name": "Living room"}}]"#,
+                               r#"[{"success": {"/scenes/ab341ef24/name": "Evening"}}]"#]);
+    let renamed = bridge.rename_group(2, "Living room".to_owned()).unwrap();
+    assert_eq!(renamed[0]["/groups/2/name"], JsonValue::from("Living room"));
+    let renamed = bridge.rename_scene("ab341ef24", "Evening".to_owned()).unwrap();
+    assert_eq!(renamed[0]["/scenes/ab341ef24/name"], JsonValue::from("Evening"));
+    assert_eq!(bridge.transport.requests(), vec![
+        ("PUT", "http://192.168.2.23/api/hello/groups/2", r#"{"name":"Living room"}"#).into(),
+        ("PUT", "http://192.168.2.23/api/hello/scenes/ab341ef24", r#"{"name":"Evening"}"#).into(),
+    ]);
+}
+
+#[test]
+fn try_get_missing_resources() {
+    let bridge = stub_bridge(&[r#"[{"error": {
+        "type": 3, "address": "/lights/999", "description": "resource, /lights/999, not available"
+    }}]"#]);
+    assert!(bridge.try_get_light(999).unwrap().is_none());
+    assert!(bridge.try_get_group_attributes(999).unwrap().is_none());
+    assert!(bridge.try_get_scene_with_states("missing").unwrap().is_none());
+
+    let bridge = stub_bridge(&[r#"[{"error": {"type": 1, "address": "/", "description": "unauthorized user"}}]"#]);
+    assert!(bridge.try_get_light(1).is_err());
+}
+
+#[test]
+fn create_recyclable_group() {
+    let bridge = stub_bridge(&[r#"[{"success": {"id": "7"}}]"#]);
+    let id = bridge.create_group_from(&GroupCreator {
+        name: "Temporary".to_owned(),
+        lights: vec![1, 2],
+        group_type: GroupType::LightGroup,
+        class: None,
+        recycle: Some(true),
+    }).unwrap();
+    assert_eq!(id, 7);
+    let id = bridge.create_group("Kitchen".to_owned(), vec![3], GroupType::Room, Some(RoomClass::Kitchen)).unwrap();
+    assert_eq!(id, 7);
+    assert_eq!(bridge.transport.requests(), vec![
+        ("POST", "http://192.168.2.23/api/hello/groups",
+         r#"{"name":"Temporary","lights":["1","2"],"type":"LightGroup","recycle":true}"#).into(),
+        ("POST", "http://192.168.2.23/api/hello/groups",
+         r#"{"name":"Kitchen","lights":["3"],"type":"Room","class":"Kitchen"}"#).into(),
+    ]);
+}
+
+#[test]
+fn typed_deletes() {
+    let bridge = stub_bridge(&[r#"[{"success": "/lights/3 deleted"}]"#,
+                               r#"[{"success": "/groups/12 deleted"}]"#,
+                               r#"[{"success": "/scenes/ab341ef24 deleted"}]"#]);
+    assert_eq!(bridge.delete_light_typed(3).unwrap(), Delete { resource: "lights".to_owned(), id: "3".to_owned() });
+    assert_eq!(bridge.delete_group_typed(12).unwrap().id, "12");
+    assert_eq!(bridge.delete_scene_typed("ab341ef24").unwrap().resource, "scenes");
+    let methods: Vec<_> = bridge.transport.requests().into_iter().map(|r| r.method).collect();
+    assert_eq!(methods, vec!["DELETE"; 3]);
+
+    let bridge = stub_bridge(&[r#"[]"#]);
+    assert!(bridge.delete_group_typed(12).is_err());
+}
+
+#[test]
+fn report_every_error() {
+    let bridge = stub_bridge(&[r#"[
+        {"success": {"/lights/1/state/on": true}},
+        {"error": {"type": 7, "address": "/lights/1/state/bri", "description": "invalid value, 300, for parameter, bri"}},
+        {"error": {"type": 6, "address": "/lights/1/state/foo", "description": "parameter, foo, not available"}}
+    ]"#]);
+    match bridge.set_light_state(1, &LightCommand::default().on()) {
+        Err(HueError(HueErrorKind::Multiple(errors), _)) => {
+            assert_eq!(errors, vec![BridgeError::InvalidValueForParameter, BridgeError::ParameterNotAvailable])
+        }
+        r => panic!("expected multiple errors, got {:?}", r),
+    }
+}
+
+#[test]
+fn ping_bridge() {
+    let bridge = stub_bridge(&[r#"{"name": "Philips hue", "swversion": "1935074050"}"#]);
+    bridge.ping().unwrap();
+    assert_eq!(bridge.transport.requests(), vec![("GET", "http://192.168.2.23/api/hello/config", "").into()]);
+
+    let closed = ::std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().to_string();
+    match Bridge::new(closed, "hello").ping() {
+        Err(HueError(HueErrorKind::Unreachable, _)) => (),
+        r => panic!("expected unreachable, got {:?}", r),
+    }
+}
+
+#[test]
+fn recall_scene_with_transition() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/groups/0/action/scene": "ab341ef24"}}]"#]);
+    bridge.recall_scene_in_group(0, &SceneRecall::new("ab341ef24")).unwrap();
+    bridge.recall_scene_in_group_with_transition(0, "ab341ef24", 20).unwrap();
+    assert_eq!(bridge.transport.requests(), vec![
+        ("PUT", "http://192.168.2.23/api/hello/groups/0/action", r#"{"scene":"ab341ef24"}"#).into(),
+        ("PUT", "http://192.168.2.23/api/hello/groups/0/action", r#"{"scene":"ab341ef24","transitiontime":20}"#).into(),
+    ]);
+}
+
+#[test]
+fn all_lights_use_group_zero() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/groups/0/action/on": true}}]"#]);
+    bridge.all_lights_on().unwrap();
+    bridge.all_lights_off().unwrap();
+    bridge.set_all_lights_state(&LightCommand::default().with_bri(100)).unwrap();
+    assert_eq!(bridge.transport.requests(), vec![
+        ("PUT", "http://192.168.2.23/api/hello/groups/0/action", r#"{"on":true}"#).into(),
+        ("PUT", "http://192.168.2.23/api/hello/groups/0/action", r#"{"on":false}"#).into(),
+        ("PUT", "http://192.168.2.23/api/hello/groups/0/action", r#"{"bri":100}"#).into(),
+    ]);
+}
+
+#[test]
+fn count_lights_groups_and_scenes() {
+    let bridge = stub_bridge(&[r#"{
+        "1": {"state": {"on": false, "bri": 254, "alert": "none", "reachable": true}, "type": "On/Off plug-in unit",
+              "name": "Kitchen", "modelid": "LOM001", "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.04.12"},
+        "2": {"state": {"on": true, "bri": 254, "alert": "none", "reachable": true}, "type": "On/Off plug-in unit",
+              "name": "Hallway", "modelid": "LOM001", "uniqueid": "00:17:88:01:10:56:4e:6e-0b", "swversion": "1.04.12"}
+    }"#, r#"{
+        "1": {"name": "Kitchen", "lights": ["1"], "type": "Room", "class": "Kitchen",
+              "state": {"all_on": false, "any_on": false}}
+    }"#, r#"{
+        "a1": {"name": "Evening", "lights": ["1"], "owner": "ffffffffe0341b1b376a2389376a2389",
+               "recycle": false, "locked": false, "appdata": {}, "picture": "", "lastupdated": null},
+        "b2": {"name": "Morning", "lights": ["2"], "owner": "ffffffffe0341b1b376a2389376a2389",
+               "recycle": false, "locked": false, "appdata": {}, "picture": "", "lastupdated": null},
+        "c3": {"name": "Night", "lights": ["1", "2"], "owner": "ffffffffe0341b1b376a2389376a2389",
+               "recycle": true, "locked": false, "appdata": {}, "picture": "", "lastupdated": null}
+    }"#, "{}"]);
+    assert_eq!(bridge.count_lights().unwrap(), 2);
+    assert_eq!(bridge.count_groups().unwrap(), 1);
+    assert_eq!(bridge.count_scenes().unwrap(), 3);
+    assert_eq!(bridge.count_lights().unwrap(), 0);
+    let urls: Vec<_> = bridge.transport.requests().into_iter().map(|r| r.url).collect();
+    assert_eq!(urls, vec!["http://192.168.2.23/api/hello/lights",
+                          "http://192.168.2.23/api/hello/groups",
+                          "http://192.168.2.23/api/hello/scenes",
+                          "http://192.168.2.23/api/hello/lights"]);
+}
+
+#[test]
+fn verify_username() {
+    let bridge = stub_bridge(&["{}"]);
+    bridge.verify().unwrap();
+    assert_eq!(bridge.transport.requests(), vec![("GET", "http://192.168.2.23/api/hello/lights", "").into()]);
+
+    let bridge = stub_bridge(&[r#"[{"error": {"type": 1, "address": "/lights", "description": "unauthorized user"}}]"#]);
+    match bridge.verify() {
+        Err(HueError(HueErrorKind::Unauthorized, _)) => (),
+        r => panic!("expected unauthorized, got {:?}", r),
+    }
+}
+
+#[test]
+fn rate_limit_only_changes() {
+    use std::time::{Duration, Instant};
+
+    let bridge = stub_bridge(&["{}", "{}", "{}", "{}", "{}", r#"[{"success": {"/lights/1/state/on": true}}]"#])
+        .with_rate_limit_and_burst(1, 2);
+    let limiter = bridge.rate_limiter.clone().unwrap();
+    for _ in 0..5 {
+        bridge.get_all_lights().unwrap();
+    }
+    // Reading took no tokens, so one of the two is still left after taking one here
+    assert_eq!(limiter.reserve(Instant::now()), Duration::from_secs(0));
+    bridge.set_light_state(1, &LightCommand::default().on()).unwrap();
+    assert!(limiter.reserve(Instant::now()) > Duration::from_millis(500));
+}
+
+#[test]
+fn current_user_from_whitelist() {
+    let bridge = stub_bridge(&[CONFIG_JSON]).as_user("83b7780291a6ceffbe0bd049104df");
+    let user = bridge.get_current_user().unwrap();
+    assert_eq!(user.name, "my_hue_app#homepc");
+    assert_eq!(bridge.transport.requests(), vec![("GET", "http://192.168.2.23/api/83b7780291a6ceffbe0bd049104df/config", "").into()]);
+
+    match stub_bridge(&[CONFIG_JSON]).get_current_user() {
+        Err(HueError(HueErrorKind::BridgeError{error: BridgeError::ResourceNotAvailable, ..}, _)) => (),
+        r => panic!("expected resource not available, got {:?}", r),
+    }
+}
+
+#[test]
+fn brightness_ramp_through_bridge() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/lights/1/state/bri_inc": 20}}]"#,
+                               r#"[{"success": {"/lights/1/state/bri": 254}}]"#]);
+    let ramp = bridge.start_brightness_ramp(1, 20, Duration::from_millis(0)).unwrap();
+    // The ramp stops by itself once the light reports its maximum brightness
+    ramp.handle.join().unwrap();
+    assert_eq!(bridge.transport.requests(), vec![
+        ("PUT", "http://192.168.2.23/api/hello/lights/1/state", r#"{"bri_inc":20}"#).into(),
+        ("PUT", "http://192.168.2.23/api/hello/lights/1/state", r#"{"bri_inc":20}"#).into(),
+    ]);
+}
+
+#[test]
+fn confirm_light_state() {
+    const SET: &str = r#"[{"success": {"/lights/1/state/bri": 200}}]"#;
+    const DIM: &str = r#"{"state": {"on": true, "bri": 100, "alert": "none", "reachable": true}, "type": "Dimmable light",
+        "name": "Kitchen", "modelid": "LWB010", "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.46.13"}"#;
+    const BRIGHT: &str = r#"{"state": {"on": true, "bri": 200, "alert": "none", "reachable": true}, "type": "Dimmable light",
+        "name": "Kitchen", "modelid": "LWB010", "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.46.13"}"#;
+    let command = LightCommand::default().with_bri(200);
+
+    let bridge = stub_bridge(&[SET, DIM, SET, BRIGHT]);
+    assert_eq!(bridge.set_light_state_confirmed(1, &command, 3).unwrap().bri, Some(200));
+    assert_eq!(bridge.transport.requests().len(), 4);
+
+    let bridge = stub_bridge(&[SET, DIM, SET, DIM]);
+    assert_eq!(bridge.set_light_state_confirmed(1, &command, 1).unwrap().bri, Some(100));
+    let methods: Vec<_> = bridge.transport.requests().into_iter().map(|r| r.method).collect();
+    assert_eq!(methods, vec!["PUT", "GET", "PUT", "GET"]);
+
+    const BUSY: &str = r#"[{"error": {"type": 901, "address": "/lights/1/state", "description": "Internal error, 404"}}]"#;
+    let bridge = stub_bridge(&[BUSY, SET, BRIGHT]);
+    assert_eq!(bridge.set_light_state_confirmed(1, &command, 1).unwrap().bri, Some(200));
+    let methods: Vec<_> = bridge.transport.requests().into_iter().map(|r| r.method).collect();
+    assert_eq!(methods, vec!["PUT", "PUT", "GET"]);
+
+    let bridge = stub_bridge(&[BUSY]);
+    assert!(bridge.set_light_state_confirmed(1, &command, 2).is_err());
+    assert_eq!(bridge.transport.requests().len(), 3);
+
+    const UNAUTHORIZED: &str = r#"[{"error": {"type": 1, "address": "/lights/1/state", "description": "unauthorized user"}}]"#;
+    let bridge = stub_bridge(&[UNAUTHORIZED]);
+    assert!(bridge.set_light_state_confirmed(1, &command, 2).is_err());
+    assert_eq!(bridge.transport.requests().len(), 1);
+}
+
+#[test]
+fn timezones() {
+    let bridge = stub_bridge(&[r#"[
+        "CET", "UTC", "GMT", "Europe/Amsterdam", "America/Argentina/Buenos_Aires"
+    ]"#]);
+    let timezones = bridge.get_timezones().unwrap();
+    assert_eq!(timezones.len(), 5);
+    assert!(timezones.iter().any(|tz| tz == "Europe/Amsterdam"));
+    assert_eq!(bridge.transport.requests(), vec![("GET", "http://192.168.2.23/api/hello/info/timezones", "").into()]);
+}
+
+#[test]
+fn find_by_name_ignoring_case() {
+    let bridge = stub_bridge(&[r#"{
+        "1": {"state": {"on": false, "bri": 254, "alert": "none", "reachable": true}, "type": "On/Off plug-in unit",
+              "name": "Kitchen", "modelid": "LOM001", "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.04.12"},
+        "2": {"state": {"on": true, "bri": 254, "alert": "none", "reachable": true}, "type": "On/Off plug-in unit",
+              "name": "Living room", "modelid": "LOM001", "uniqueid": "00:17:88:01:10:56:4e:6e-0b", "swversion": "1.04.12"},
+        "3": {"state": {"on": true, "bri": 254, "alert": "none", "reachable": true}, "type": "On/Off plug-in unit",
+              "name": "living ROOM", "modelid": "LOM001", "uniqueid": "00:17:88:01:10:56:4e:6f-0b", "swversion": "1.04.12"}
+    }"#]);
+    let (id, light) = bridge.find_light_by_name("LIVING room").unwrap().unwrap();
+    assert_eq!(id, 2);
+    assert!(light.state.on);
+    assert_eq!(bridge.find_light_by_name("kitchen").unwrap().map(|(id, _)| id), Some(1));
+    assert!(bridge.find_light_by_name("Bedroom").unwrap().is_none());
+
+    let bridge = stub_bridge(&[r#"{
+        "1": {"name": "Kitchen", "lights": ["1"], "type": "Room", "class": "Kitchen",
+              "state": {"all_on": false, "any_on": false}},
+        "2": {"name": "Upstairs", "lights": ["2", "3"], "type": "LightGroup",
+              "state": {"all_on": true, "any_on": true}}
+    }"#]);
+    assert_eq!(bridge.find_group_by_name("upSTAIRS").unwrap().map(|(id, _)| id), Some(2));
+    assert!(bridge.find_group_by_name("Garden").unwrap().is_none());
+    assert_eq!(bridge.transport.requests().len(), 2);
+    assert_eq!(bridge.transport.requests()[0].url, "http://192.168.2.23/api/hello/groups");
+}
+
+#[test]
+fn set_light_config() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/lights/3/config/archetype": "sultanbulb"}},
+                                  {"success": {"/lights/3/config/direction": "downwards"}}]"#]);
+    let modifier = LightConfigModifier {
+        archetype: Some("sultanbulb".to_owned()),
+        direction: Some("downwards".to_owned()),
+        ..Default::default()
+    };
+    let changed = bridge.set_light_config(3, &modifier).unwrap();
+    assert_eq!(changed.len(), 2);
+    assert_eq!(changed[0]["/lights/3/config/archetype"], JsonValue::from("sultanbulb"));
+    assert_eq!(bridge.transport.requests(), vec![
+        ("PUT", "http://192.168.2.23/api/hello/lights/3",
+         r#"{"config":{"archetype":"sultanbulb","direction":"downwards"}}"#).into(),
+    ]);
+}
+
+#[test]
+fn state_in_parallel() {
+    let routes = [
+        ("http://192.168.2.23/api/hello/lights", r#"{"1": {
+            "state": {"on": false, "bri": 254, "alert": "none", "reachable": true}, "type": "On/Off plug-in unit",
+            "name": "Plug", "modelid": "LOM001", "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.04.12"
+        }}"#),
+        ("http://192.168.2.23/api/hello/groups", "{}"),
+        ("http://192.168.2.23/api/hello/scenes", "{}"),
+        ("http://192.168.2.23/api/hello/config", CONFIG_JSON),
+        ("http://192.168.2.23/api/hello/schedules", "{}"),
+        ("http://192.168.2.23/api/hello/sensors", "{}"),
+        ("http://192.168.2.23/api/hello/rules", r#"{"1": {"name": "Wall switch"}}"#),
+    ];
+    let bridge = Bridge::with_transport(StubTransport::with_routes(&routes), "192.168.2.23", "hello");
+    let state = bridge.get_state_parallel().unwrap();
+    assert_eq!(state.lights.keys().cloned().collect::<Vec<_>>(), vec![1]);
+    assert_eq!(state.lights[&1].name, "Plug");
+    assert!(state.groups.is_empty());
+    assert!(state.scenes.is_empty());
+    assert_eq!(state.config.name, "Philips hue");
+    assert_eq!(state.rules["1"]["name"], JsonValue::from("Wall switch"));
+
+    let mut urls: Vec<_> = bridge.transport.requests().into_iter().map(|r| r.url).collect();
+    urls.sort();
+    let mut expected: Vec<_> = routes.iter().map(|&(url, _)| url.to_owned()).collect();
+    expected.sort();
+    assert_eq!(urls, expected);
+
+    let mut routes = routes;
+    routes[2].1 = "bridge busy";
+    let bridge = Bridge::with_transport(StubTransport::with_routes(&routes), "192.168.2.23", "hello");
+    assert!(bridge.get_state_parallel().unwrap_err().to_string().contains("scenes"));
+}
+
+#[test]
+fn group_state() {
+    let bridge = stub_bridge(&[r#"{
+        "name": "Living room", "lights": ["1", "2"], "type": "Room", "class": "Living room",
+        "state": {"all_on": false, "any_on": true}, "recycle": false,
+        "action": {"on": true, "bri": 144, "alert": "none"}
+    }"#, r#"{"name": "Old group", "lights": ["1"], "type": "LightGroup"}"#]);
+    let state = bridge.get_group_state(1).unwrap().unwrap();
+    assert!(state.any_on);
+    assert_eq!(state.status(), GroupOnStatus::Mixed);
+    assert!(bridge.get_group_state(2).unwrap().is_none());
+    let urls: Vec<_> = bridge.transport.requests().into_iter().map(|r| r.url).collect();
+    assert_eq!(urls, vec!["http://192.168.2.23/api/hello/groups/1", "http://192.168.2.23/api/hello/groups/2"]);
+}
+
+#[test]
+fn create_button_rule() {
+    let bridge = stub_bridge(&[r#"[{"success": {"id": "4"}}]"#]);
+    let rule = RuleBuilder::on_button_event(2, 34).recall_scene(0, "S3").build("Tap 2.1 Default");
+    assert_eq!(bridge.create_rule(&rule).unwrap(), "4");
+    let requests = bridge.transport.requests();
+    assert_eq!((&*requests[0].method, &*requests[0].url), ("POST", "http://192.168.2.23/api/hello/rules"));
+    assert_eq!(::serde_json::from_str::<JsonValue>(&requests[0].body).unwrap(), ::serde_json::to_value(&rule).unwrap());
+}
+
+#[test]
+fn scene_light_explicitly_off() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/scenes/ab341ef24/lightstates/1/on": false}}]"#,
+                               r#"[{"success": {"/scenes/ab341ef24/lightstates/2/bri": 100}}]"#]);
+    let off = LightStateChange {
+        on: Some(false),
+        bri: None,
+        hue: None,
+        sat: None,
+        xy: None,
+        ct: None,
+        alert: None,
+        effect: None,
+        colormode: None,
+    };
+    bridge.set_light_state_in_scene("ab341ef24", 1, &off).unwrap();
+    let unmanaged = LightStateChange { on: None, bri: Some(100), ..off };
+    bridge.set_light_state_in_scene("ab341ef24", 2, &unmanaged).unwrap();
+    assert_eq!(bridge.transport.requests(), vec![
+        ("PUT", "http://192.168.2.23/api/hello/scenes/ab341ef24/lightstates/1", r#"{"on":false}"#).into(),
+        ("PUT", "http://192.168.2.23/api/hello/scenes/ab341ef24/lightstates/2", r#"{"bri":100}"#).into(),
+    ]);
+}
+
+#[test]
+fn create_group_then_fetch() {
+    let bridge = stub_bridge(&[r#"[{"success": {"id": "3"}}]"#, r#"{
+        "name": "Kitchen", "lights": ["4", "5"], "type": "Room", "class": "Kitchen",
+        "state": {"all_on": false, "any_on": false}
+    }"#]);
+    let kitchen = GroupCreator {
+        name: "Kitchen".to_owned(),
+        lights: vec![4, 5],
+        group_type: GroupType::Room,
+        class: Some(RoomClass::Kitchen),
+        recycle: None,
+    };
+    let (id, group) = bridge.create_group_full(&kitchen).unwrap();
+    assert_eq!(id, 3);
+    assert_eq!(group.name, "Kitchen");
+    assert_eq!(group.lights, vec![4, 5]);
+    assert_eq!(bridge.transport.requests(), vec![
+        ("POST", "http://192.168.2.23/api/hello/groups",
+         r#"{"name":"Kitchen","lights":["4","5"],"type":"Room","class":"Kitchen"}"#).into(),
+        ("GET", "http://192.168.2.23/api/hello/groups/3", "").into(),
+    ]);
+
+    let bridge = stub_bridge(&[r#"[{"error": {"type": 301, "address": "/groups", "description": "group table full"}}]"#]);
+    assert!(bridge.create_group_full(&kitchen).is_err());
+    assert_eq!(bridge.transport.requests().len(), 1);
+}
+
+#[test]
+fn sorted_ids() {
+    let bridge = stub_bridge(&[r#"{
+        "10": {"state": {"on": false, "bri": 254, "alert": "none", "reachable": true}, "type": "On/Off plug-in unit",
+               "name": "Ten", "modelid": "LOM001", "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.04.12"},
+        "2": {"state": {"on": false, "bri": 254, "alert": "none", "reachable": true}, "type": "On/Off plug-in unit",
+              "name": "Two", "modelid": "LOM001", "uniqueid": "00:17:88:01:10:56:4e:6e-0b", "swversion": "1.04.12"}
+    }"#, r#"{
+        "11": {"name": "Upstairs", "lights": ["10"], "type": "LightGroup"},
+        "3": {"name": "Kitchen", "lights": ["2"], "type": "LightGroup"}
+    }"#, r#"{
+        "b2": {"name": "Relax", "lights": ["2"], "owner": "ffffffffe0341b1b376a2389376a2389",
+               "recycle": false, "locked": false, "appdata": {}, "picture": "", "lastupdated": null},
+        "a1": {"name": "Relax", "lights": ["10"], "owner": "ffffffffe0341b1b376a2389376a2389",
+               "recycle": false, "locked": false, "appdata": {}, "picture": "", "lastupdated": null},
+        "c3": {"name": "Concentrate", "lights": ["2"], "owner": "ffffffffe0341b1b376a2389376a2389",
+               "recycle": false, "locked": false, "appdata": {}, "picture": "", "lastupdated": null}
+    }"#]);
+    let lights: Vec<_> = bridge.get_all_lights_sorted().unwrap().into_iter().map(|(id, l)| (id, l.name)).collect();
+    assert_eq!(lights, vec![(2, "Two".to_owned()), (10, "Ten".to_owned())]);
+    let groups: Vec<_> = bridge.get_all_groups_sorted().unwrap().into_iter().map(|(id, _)| id).collect();
+    assert_eq!(groups, vec![3, 11]);
+    let scenes: Vec<_> = bridge.get_all_scenes_sorted().unwrap().into_iter().map(|(id, _)| id).collect();
+    assert_eq!(scenes, vec!["c3", "a1", "b2"]);
+}
+
+#[test]
+fn delete_user_typed() {
+    let bridge = stub_bridge(&[r#"[{"success": "/config/whitelist/1028d66426293e821ecfd9ef1a0731df deleted"}]"#,
+                               r#"[{"success": "/lights/3 deleted"}]"#]);
+    let user = bridge.delete_user_typed("1028d66426293e821ecfd9ef1a0731df").unwrap();
+    assert_eq!(user, UserDeleted { username: "1028d66426293e821ecfd9ef1a0731df".to_owned() });
+    assert!(bridge.delete_user_typed("1028d66426293e821ecfd9ef1a0731df").is_err());
+    assert_eq!(bridge.transport.requests()[0],
+               ("DELETE", "http://192.168.2.23/api/hello/config/whitelist/1028d66426293e821ecfd9ef1a0731df", "").into());
+}
+
+#[test]
+fn light_color_in_gamut() {
+    let bridge = stub_bridge(&[r#"{
+        "state": {"on": true, "bri": 254, "hue": 0, "sat": 0, "xy": [0.3, 0.3], "alert": "none", "reachable": true},
+        "type": "Extended color light", "name": "Hue Lamp 1", "modelid": "LCT001",
+        "uniqueid": "00:17:88:01:00:bd:c7:b9-0b", "swversion": "5.105.0.21169"
+    }"#, r#"[{"success": {"/lights/1/state/xy": [0.4091, 0.518]}}]"#]);
+    bridge.set_light_color(1, (0, 255, 0)).unwrap();
+    let requests = bridge.transport.requests();
+    assert_eq!(requests[0], ("GET", "http://192.168.2.23/api/hello/lights/1", "").into());
+    assert_eq!((&*requests[1].method, &*requests[1].url), ("PUT", "http://192.168.2.23/api/hello/lights/1/state"));
+    // Pure green is outside gamut B, so it's moved onto its edge
+    let sent: LightCommand = ::serde_json::from_str(&requests[1].body).unwrap();
+    let expected = color::rgb_to_xy_in_gamut((0, 255, 0), color::GAMUT_B);
+    assert!((sent.xy.unwrap().x - expected.x).abs() < 0.0001 && (sent.xy.unwrap().y - expected.y).abs() < 0.0001);
+    assert_ne!(expected, color::rgb_to_xy_in_gamut((0, 255, 0), color::GAMUT_C));
+}
+
+#[test]
+fn unreachable_light_short_circuits() {
+    let bridge = stub_bridge(&[r#"{
+        "state": {"on": false, "bri": 254, "alert": "none", "reachable": true}, "type": "Dimmable light",
+        "name": "Porch", "modelid": "LWB010", "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.04.12"
+    }"#, r#"[{"success": {"/lights/3/state/on": true}}]"#, r#"{
+        "state": {"on": true, "bri": 254, "alert": "none", "reachable": false}, "type": "Dimmable light",
+        "name": "Porch", "modelid": "LWB010", "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.04.12"
+    }"#]);
+    bridge.set_light_state_checked(3, &LightCommand::default().on()).unwrap();
+    match bridge.set_light_state_checked(3, &LightCommand::default().off()) {
+        Err(HueError(HueErrorKind::BridgeError { error: BridgeError::DeviceIsUnreachable, ref address, .. }, _)) => {
+            assert_eq!(address, "/lights/3/state")
+        }
+        r => panic!("expected unreachable device, got {:?}", r),
+    }
+    let methods: Vec<_> = bridge.transport.requests().into_iter().map(|r| r.method).collect();
+    assert_eq!(methods, vec!["GET", "PUT", "GET"]);
+}
+
+#[test]
+fn watch_light_changes() {
+    const DIM: &str = r#"{
+        "1": {"state": {"on": true, "bri": 254, "alert": "none", "reachable": true}, "type": "On/Off plug-in unit",
+              "name": "Plug", "modelid": "LOM001", "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.04.12"},
+        "2": {"state": {"on": true, "bri": 100, "alert": "none", "reachable": true}, "type": "Dimmable light",
+              "name": "Lamp", "modelid": "LWB010", "uniqueid": "00:17:88:01:10:56:4e:6e-0b", "swversion": "1.04.12"}
+    }"#;
+    const BRIGHT: &str = r#"{
+        "1": {"state": {"on": true, "bri": 254, "alert": "none", "reachable": true}, "type": "On/Off plug-in unit",
+              "name": "Plug", "modelid": "LOM001", "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.04.12"},
+        "2": {"state": {"on": true, "bri": 200, "alert": "none", "reachable": true}, "type": "Dimmable light",
+              "name": "Lamp", "modelid": "LWB010", "uniqueid": "00:17:88:01:10:56:4e:6e-0b", "swversion": "1.04.12"},
+        "3": {"state": {"on": false, "bri": 254, "alert": "none", "reachable": true}, "type": "Dimmable light",
+              "name": "New lamp", "modelid": "LWB010", "uniqueid": "00:17:88:01:10:56:4e:6f-0b", "swversion": "1.04.12"}
+    }"#;
+    let bridge = stub_bridge(&[DIM, DIM, BRIGHT]);
+
+    let watch = bridge.watch_lights(Duration::from_millis(1));
+    let change = watch.recv_timeout(Duration::from_secs(5)).unwrap();
+    assert_eq!(change.id, 2);
+    assert_eq!(change.old.bri, Some(100));
+    assert_eq!(change.new.bri, Some(200));
+    // The last response keeps being repeated, which isn't a change, and neither is the added light
+    assert_eq!(watch.recv_timeout(Duration::from_millis(50)).unwrap_err(), RecvTimeoutError::Timeout);
+
+    watch.stop();
+    assert_eq!(Arc::strong_count(&bridge.transport), 1);
+
+    // Dropping the handle ends the thread without waiting for the interval to pass
+    let watch = bridge.watch_lights(Duration::from_secs(60));
+    assert_eq!(Arc::strong_count(&bridge.transport), 2);
+    drop(watch);
+    let mut waited = 0;
+    while Arc::strong_count(&bridge.transport) > 1 {
+        assert!(waited < 5000, "the polling thread is still running");
+        thread::sleep(Duration::from_millis(1));
+        waited += 1;
+    }
+}
+
+#[test]
+fn delete_only_recyclable_scenes() {
+    const SCENES: &str = r#"{
+        "a1": {"name": "Relax", "lights": ["1"], "owner": "ffffffffe0341b1b376a2389376a2389",
+               "recycle": true, "locked": false, "appdata": {}, "picture": "", "lastupdated": null},
+        "b2": {"name": "Relax", "lights": ["1"], "owner": "ffffffffe0341b1b376a2389376a2389",
+               "recycle": false, "locked": false, "appdata": {}, "picture": "", "lastupdated": null},
+        "c3": {"name": "Relax", "lights": ["1"], "owner": "ffffffffe0341b1b376a2389376a2389",
+               "recycle": true, "locked": false, "appdata": {}, "picture": "", "lastupdated": null}
+    }"#;
+    let bridge = Bridge::with_transport(StubTransport::with_routes(&[
+        ("http://192.168.2.23/api/hello/scenes", SCENES),
+        ("http://192.168.2.23/api/hello/scenes/a1", r#"[{"success": "/scenes/a1 deleted"}]"#),
+        ("http://192.168.2.23/api/hello/scenes/c3", r#"[{"success": "/scenes/c3 deleted"}]"#),
+    ]), "192.168.2.23", "hello");
+    assert_eq!(bridge.delete_recyclable_scenes().unwrap(), vec!["a1", "c3"]);
+    assert_eq!(bridge.transport.requests(), vec![
+        ("GET", "http://192.168.2.23/api/hello/scenes", "").into(),
+        ("DELETE", "http://192.168.2.23/api/hello/scenes/a1", "").into(),
+        ("DELETE", "http://192.168.2.23/api/hello/scenes/c3", "").into(),
+    ]);
+
+    let bridge = Bridge::with_transport(StubTransport::with_routes(&[
+        ("http://192.168.2.23/api/hello/scenes", SCENES),
+        ("http://192.168.2.23/api/hello/scenes/a1",
+         r#"[{"error": {"type": 403, "address": "/scenes/a1", "description": "Scene could not be removed"}}]"#),
+        ("http://192.168.2.23/api/hello/scenes/c3", r#"[{"success": "/scenes/c3 deleted"}]"#),
+    ]), "192.168.2.23", "hello");
+    match bridge.delete_recyclable_scenes() {
+        Err(HueError(HueErrorKind::BridgeError{error: BridgeError::SceneCouldNotBeRemoved, ..}, _)) => (),
+        r => panic!("expected scene removal error, got {:?}", r),
+    }
+    // The failure doesn't stop the other scene from being deleted
+    assert_eq!(bridge.transport.requests().len(), 3);
+}
+
+#[test]
+fn rename_each_light() {
+    let bridge = Bridge::with_transport(StubTransport::with_routes(&[
+        ("http://192.168.2.23/api/hello/lights/1", r#"[{"success": {"/lights/1/name": "Kitchen"}}]"#),
+        ("http://192.168.2.23/api/hello/lights/2",
+         r#"[{"error": {"type": 3, "address": "/lights/2", "description": "resource, /lights/2, not available"}}]"#),
+        ("http://192.168.2.23/api/hello/lights/3", r#"[{"success": {"/lights/3/name": "Bedroom"}}]"#),
+    ]), "192.168.2.23", "hello");
+    let names: BTreeMap<usize, String> = vec![
+        (1, "Kitchen".to_owned()),
+        (2, "Hallway".to_owned()),
+        (3, "Bedroom".to_owned()),
+    ].into_iter().collect();
+
+    let results = bridge.rename_lights(&names);
+    assert_eq!(results.len(), 3);
+    assert_eq!(results[0].1.as_ref().unwrap()[0]["/lights/1/name"], "Kitchen");
+    assert!(results[1].1.is_err());
+    assert_eq!(results[2].0, 3);
+    assert_eq!(results[2].1.as_ref().unwrap()[0]["/lights/3/name"], "Bedroom");
+    assert_eq!(bridge.transport.requests()[1],
+               ("PUT", "http://192.168.2.23/api/hello/lights/2", r#"{"name":"Hallway"}"#).into());
+}
+
+#[test]
+fn toggle_light_and_group() {
+    let bridge = stub_bridge(&[r#"{
+        "state": {"on": true, "bri": 254, "alert": "none", "reachable": true},
+        "type": "Dimmable light", "name": "Kitchen", "modelid": "LWB010",
+        "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.04.12"
+    }"#, r#"[{"success": {"/lights/1/state/on": false}}]"#, r#"{
+        "state": {"on": false, "bri": 254, "alert": "none", "reachable": true},
+        "type": "Dimmable light", "name": "Kitchen", "modelid": "LWB010",
+        "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.04.12"
+    }"#, r#"[{"success": {"/lights/1/state/on": true}}]"#]);
+    let off = bridge.toggle_light(1).unwrap();
+    assert_eq!(off[0]["/lights/1/state/on"], JsonValue::Bool(false));
+    let on = bridge.toggle_light(1).unwrap();
+    assert_eq!(on[0]["/lights/1/state/on"], JsonValue::Bool(true));
+    assert_eq!(bridge.transport.requests(), vec![
+        ("GET", "http://192.168.2.23/api/hello/lights/1", "").into(),
+        ("PUT", "http://192.168.2.23/api/hello/lights/1/state", r#"{"on":false}"#).into(),
+        ("GET", "http://192.168.2.23/api/hello/lights/1", "").into(),
+        ("PUT", "http://192.168.2.23/api/hello/lights/1/state", r#"{"on":true}"#).into(),
+    ]);
+
+    let bridge = stub_bridge(&[r#"{
+        "name": "Kitchen", "lights": ["1", "2"], "type": "Room", "class": "Kitchen",
+        "state": {"all_on": false, "any_on": false}
+    }"#, r#"[{"success": {"/groups/1/action/on": true}}]"#, r#"{
+        "name": "Kitchen", "lights": ["1", "2"], "type": "Room", "class": "Kitchen",
+        "state": {"all_on": false, "any_on": true}
+    }"#, r#"[{"success": {"/groups/1/action/on": false}}]"#]);
+    let on = bridge.toggle_group(1).unwrap();
+    assert_eq!(on[0]["/groups/1/action/on"], JsonValue::Bool(true));
+    let off = bridge.toggle_group(1).unwrap();
+    assert_eq!(off[0]["/groups/1/action/on"], JsonValue::Bool(false));
+    let requests = bridge.transport.requests();
+    assert_eq!(requests[1], ("PUT", "http://192.168.2.23/api/hello/groups/1/action", r#"{"on":true}"#).into());
+    assert_eq!(requests[3], ("PUT", "http://192.168.2.23/api/hello/groups/1/action", r#"{"on":false}"#).into());
+}
+
+#[test]
+fn delete_groups_of_one_type() {
+    const GROUPS: &str = r#"{
+        "1": {"name": "Lamp", "lights": ["1"], "type": "Luminaire"},
+        "2": {"name": "Kitchen", "lights": ["1"], "type": "Room"},
+        "3": {"name": "Upstairs", "lights": ["1"], "type": "LightGroup"},
+        "4": {"name": "Hallway", "lights": ["1"], "type": "Room"},
+        "5": {"name": "Bulb", "lights": ["1"], "type": "LightSource"},
+        "6": {"name": "Bedroom", "lights": ["1"], "type": "Room"}
+    }"#;
+    let bridge = Bridge::with_transport(StubTransport::with_routes(&[
+        ("http://192.168.2.23/api/hello/groups", GROUPS),
+        ("http://192.168.2.23/api/hello/groups/2", r#"[{"success": "/groups/2 deleted"}]"#),
+        ("http://192.168.2.23/api/hello/groups/4",
+         r#"[{"error": {"type": 305, "address": "/groups/4", "description": "Not allowed to update or delete group of this type"}}]"#),
+        ("http://192.168.2.23/api/hello/groups/6", r#"[{"success": "/groups/6 deleted"}]"#),
+    ]), "192.168.2.23", "hello");
+    assert_eq!(bridge.delete_groups_of_type(GroupType::Room).unwrap(), vec![2, 6]);
+    let deletes: Vec<_> = bridge.transport.requests().into_iter().filter(|r| r.method == "DELETE").map(|r| r.url).collect();
+    assert_eq!(deletes, vec![
+        "http://192.168.2.23/api/hello/groups/2",
+        "http://192.168.2.23/api/hello/groups/4",
+        "http://192.168.2.23/api/hello/groups/6",
+    ]);
+
+    let bridge = stub_bridge(&[GROUPS]);
+    assert_eq!(bridge.delete_groups_of_type(GroupType::Luminaire).unwrap(), Vec::<usize>::new());
+    assert!(bridge.transport.requests().is_empty());
+
+    let bridge = stub_bridge(&[GROUPS, r#"[{"error": {"type": 3, "address": "/groups/3", "description": "resource, /groups/3, not available"}}]"#]);
+    assert!(bridge.delete_groups_of_type(GroupType::LightGroup).is_err());
+}
+
+#[test]
+fn empty_commands_are_not_sent() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/lights/1/state/bri": 100}}]"#]);
+    for result in [bridge.set_light_state(1, &LightCommand::default()),
+                       bridge.set_group_state(1, &LightCommand::default().with_transitiontime(10))] {
+        match result {
+            Err(HueError(HueErrorKind::EmptyCommand, _)) => (),
+            r => panic!("expected empty command, got {:?}", r),
+        }
+    }
+    assert!(bridge.transport.requests().is_empty());
+
+    bridge.set_light_state(1, &LightCommand::default().with_bri(100)).unwrap();
+    assert_eq!(bridge.transport.requests().len(), 1);
+}
+
+#[test]
+fn only_class_is_sent() {
+    let bridge = stub_bridge(&[r#"[{"success": {"/groups/1/class": "Living room"}}]"#]);
+    let successes = bridge.set_group_class(1, RoomClass::LivingRoom).unwrap();
+    assert_eq!(successes[0]["/groups/1/class"], "Living room");
+    bridge.set_group_class(2, RoomClass::Kitchen).unwrap();
+    assert_eq!(bridge.transport.requests(), vec![
+        ("PUT", "http://192.168.2.23/api/hello/groups/1", r#"{"class":"Living room"}"#).into(),
+        ("PUT", "http://192.168.2.23/api/hello/groups/2", r#"{"class":"Kitchen"}"#).into(),
+    ]);
+}
+
+#[test]
+fn rooms_of_groups() {
+    let bridge = stub_bridge(&[r#"{
+        "1": {"name": "Hue go", "lights": ["1"], "type": "Luminaire"},
+        "2": {"name": "Kitchen", "lights": ["2", "3"], "type": "Room", "class": "Kitchen"},
+        "3": {"name": "Downstairs", "lights": ["2", "4"], "type": "LightGroup"},
+        "4": {"name": "Bedroom", "lights": ["4"], "type": "Room", "class": "Bedroom"}
+    }"#]);
+    let rooms = bridge.get_rooms().unwrap();
+    assert_eq!(rooms.keys().cloned().collect::<Vec<_>>(), vec![2, 4]);
+    assert_eq!(rooms[&4].room_class(), Some(RoomClass::Bedroom));
+    assert_eq!(bridge.transport.requests(), vec![("GET", "http://192.168.2.23/api/hello/groups", "").into()]);
+}
+
+#[test]
+fn auto_on_injection() {
+    let mut bridge = stub_bridge(&[r#"[{"success": {"/lights/1/state/on": true}}]"#]);
+    let colour = LightCommand::default().with_ct(366).with_bri(200);
+    bridge.set_light_state(1, &colour).unwrap();
+    bridge.set_auto_on(true);
+    bridge.set_light_state(1, &colour).unwrap();
+    bridge.set_group_state(2, &colour).unwrap();
+    bridge.set_light_state(1, &LightCommand::default().with_bri(200).off()).unwrap();
+    bridge.set_light_state(1, &LightCommand::default().with_alert(Alert::Select)).unwrap();
+    let bodies: Vec<_> = bridge.transport.requests().into_iter().map(|r| (r.url, r.body)).collect();
+    assert_eq!(bodies, vec![
+        ("http://192.168.2.23/api/hello/lights/1/state".to_owned(), r#"{"bri":200,"ct":366}"#.to_owned()),
+        ("http://192.168.2.23/api/hello/lights/1/state".to_owned(), r#"{"on":true,"bri":200,"ct":366}"#.to_owned()),
+        ("http://192.168.2.23/api/hello/groups/2/action".to_owned(), r#"{"on":true,"bri":200,"ct":366}"#.to_owned()),
+        ("http://192.168.2.23/api/hello/lights/1/state".to_owned(), r#"{"on":false,"bri":200}"#.to_owned()),
+        ("http://192.168.2.23/api/hello/lights/1/state".to_owned(), r#"{"alert":"select"}"#.to_owned()),
+    ]);
+}
+
+#[test]
+fn detailed_state_responses() {
+    let bridge = stub_bridge(&[r#"[
+        {"success": {"/lights/1/state/on": true}},
+        {"error": {"type": 201, "address": "/lights/1/state/hue", "description": "parameter, hue, is not modifiable. Device is set to off."}}
+    ]"#]);
+    let response = bridge.set_light_state_detailed(1, &LightCommand::default().on().with_hue(8418)).unwrap();
+    assert_eq!(response.errors().len(), 1);
+    let addresses: Vec<_> = response.into_iter().map(|s| s.address).collect();
+    assert_eq!(addresses, vec!["/lights/1/state/on"]);
+
+    let response = bridge.set_group_state_detailed(2, &LightCommand::default().on()).unwrap();
+    assert!(!response.is_ok());
+    assert!(bridge.set_group_state_detailed(2, &LightCommand::default()).is_err());
+    assert_eq!(bridge.transport.requests(), vec![
+        ("PUT", "http://192.168.2.23/api/hello/lights/1/state", r#"{"on":true,"hue":8418}"#).into(),
+        ("PUT", "http://192.168.2.23/api/hello/groups/2/action", r#"{"on":true}"#).into(),
+    ]);
+}
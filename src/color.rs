@@ -0,0 +1,273 @@
+//! The conversions follow the ones described in the
+//! [Hue documentation](https://developers.meethue.com/documentation/color-conversions-rgb-xy):
+//! colours are treated as sRGB, converted to linear RGB and then to the CIE XYZ space using
+//! the Wide RGB D65 matrices. Hue and saturation are interpreted as HSV with full value, so
+//! the conversions are approximations that ignore brightness and the gamut of the light.
+
+use crate::hue::Xy;
+
+/// Converts a hue (0-65535) and saturation (0-254) to x and y coordinates in CIE space
+pub fn hs_to_xy(hue: u16, sat: u8) -> Xy {
+    let (r, g, b) = hsv_to_rgb(f32::from(hue) / 65535.0 * 360.0, f32::from(sat) / 254.0);
+    rgb_to_xy(r, g, b)
+}
+
+/// Converts x and y coordinates in CIE space to a hue (0-65535) and saturation (0-254)
+pub fn xy_to_hs(xy: Xy) -> (u16, u8) {
+    let (r, g, b) = xy_to_rgb(xy);
+    let (hue, sat) = rgb_to_hs(r, g, b);
+    ((hue / 360.0 * 65535.0).round() as u16, (sat * 254.0).round() as u8)
+}
+
+/// Converts an sRGB colour to x and y coordinates in CIE space, moved into the gamut
+pub fn rgb_to_xy_in_gamut((r, g, b): (u8, u8, u8), gamut: Gamut) -> Xy {
+    let xy = rgb_to_xy(f32::from(r) / 255.0, f32::from(g) / 255.0, f32::from(b) / 255.0);
+    closest_point_in_gamut(xy, gamut)
+}
+
+/// Converts a [mired](http://en.wikipedia.org/wiki/Mired) colour temperature to kelvin
+///
+/// The conversion works both ways, so this also converts kelvin to mired. 0 stays 0.
+pub fn mired_to_kelvin(mired: u16) -> u16 {
+    if mired == 0 {
+        0
+    } else {
+        ((1_000_000 + u32::from(mired) / 2) / u32::from(mired)).min(u32::from(u16::MAX)) as u16
+    }
+}
+
+/// Converts a colour temperature in kelvin to [mired](http://en.wikipedia.org/wiki/Mired)
+pub fn kelvin_to_mired(kelvin: u16) -> u16 {
+    mired_to_kelvin(kelvin)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+/// The triangle of colours in CIE space a light is able to show
+pub struct Gamut {
+    /// The x and y coordinates of the reddest colour
+    pub red: Xy,
+    /// The x and y coordinates of the greenest colour
+    pub green: Xy,
+    /// The x and y coordinates of the bluest colour
+    pub blue: Xy,
+}
+
+/// Gamut of older LivingColors lights, e.g. Bloom, Iris and LightStrips
+pub const GAMUT_A: Gamut = Gamut {
+    red: Xy { x: 0.704, y: 0.296 },
+    green: Xy { x: 0.2151, y: 0.7106 },
+    blue: Xy { x: 0.138, y: 0.08 },
+};
+/// Gamut of the first generations of Hue bulbs
+pub const GAMUT_B: Gamut = Gamut {
+    red: Xy { x: 0.675, y: 0.322 },
+    green: Xy { x: 0.409, y: 0.518 },
+    blue: Xy { x: 0.167, y: 0.04 },
+};
+/// Gamut of current Hue bulbs and LightStrips Plus
+pub const GAMUT_C: Gamut = Gamut {
+    red: Xy { x: 0.692, y: 0.308 },
+    green: Xy { x: 0.17, y: 0.7 },
+    blue: Xy { x: 0.153, y: 0.048 },
+};
+
+/// The gamut of a light with the given model ID, or `None` for unknown and white-only models
+///
+/// Follows the table of supported lights in the Hue documentation.
+pub fn gamut_for_model(modelid: &str) -> Option<Gamut> {
+    match modelid {
+        "LLC001" | "LLC005" | "LLC006" | "LLC007" | "LLC010" | "LLC011" | "LLC012" | "LLC013" |
+        "LLC014" | "LST001" => Some(GAMUT_A),
+        "LCT001" | "LCT002" | "LCT003" | "LCT007" | "LLM001" => Some(GAMUT_B),
+        "LCT010" | "LCT011" | "LCT012" | "LCT014" | "LCT015" | "LCT016" | "LLC020" |
+        "LST002" => Some(GAMUT_C),
+        _ => None,
+    }
+}
+
+impl Gamut {
+    /// Whether the gamut contains the given point, including its edges
+    pub fn contains(&self, p: Xy) -> bool {
+        fn cross(a: Xy, b: Xy, p: Xy) -> f32 {
+            (b.x - a.x) * (p.y - a.y) - (b.y - a.y) * (p.x - a.x)
+        }
+        let d1 = cross(self.red, self.green, p);
+        let d2 = cross(self.green, self.blue, p);
+        let d3 = cross(self.blue, self.red, p);
+        let has_neg = d1 < 0.0 || d2 < 0.0 || d3 < 0.0;
+        let has_pos = d1 > 0.0 || d2 > 0.0 || d3 > 0.0;
+        !(has_neg && has_pos)
+    }
+}
+
+/// Returns the given point if it's inside the gamut, otherwise the closest point on its edges
+///
+/// This is the same way the lights themselves handle colours they can't show, as described in
+/// the [Hue documentation](https://developers.meethue.com/documentation/color-conversions-rgb-xy).
+pub fn closest_point_in_gamut(xy: Xy, gamut: Gamut) -> Xy {
+    fn closest_on_line(a: Xy, b: Xy, p: Xy) -> Xy {
+        let (abx, aby) = (b.x - a.x, b.y - a.y);
+        let t = ((p.x - a.x) * abx + (p.y - a.y) * aby) / (abx * abx + aby * aby);
+        let t = t.clamp(0.0, 1.0);
+        Xy { x: a.x + abx * t, y: a.y + aby * t }
+    }
+    fn distance(a: Xy, b: Xy) -> f32 {
+        ((a.x - b.x).powi(2) + (a.y - b.y).powi(2)).sqrt()
+    }
+
+    if gamut.contains(xy) {
+        return xy;
+    }
+    let candidates = [
+        closest_on_line(gamut.red, gamut.green, xy),
+        closest_on_line(gamut.green, gamut.blue, xy),
+        closest_on_line(gamut.blue, gamut.red, xy),
+    ];
+    let mut closest = candidates[0];
+    for &candidate in &candidates[1..] {
+        if distance(candidate, xy) < distance(closest, xy) {
+            closest = candidate;
+        }
+    }
+    closest
+}
+
+/// Converts RGB values between 0 and 1 to x and y coordinates
+fn rgb_to_xy(r: f32, g: f32, b: f32) -> Xy {
+    fn gamma(v: f32) -> f32 {
+        if v > 0.04045 { ((v + 0.055) / 1.055).powf(2.4) } else { v / 12.92 }
+    }
+    let (r, g, b) = (gamma(r), gamma(g), gamma(b));
+
+    let x = r * 0.664_511 + g * 0.154_324 + b * 0.162_028;
+    let y = r * 0.283_881 + g * 0.668_433 + b * 0.047_685;
+    let z = r * 0.000_088 + g * 0.072_310 + b * 0.986_039;
+
+    let sum = x + y + z;
+    if sum == 0.0 {
+        Xy::default()
+    } else {
+        Xy { x: x / sum, y: y / sum }
+    }
+}
+
+/// Converts x and y coordinates to RGB values between 0 and 1 at full brightness
+fn xy_to_rgb(Xy { x, y }: Xy) -> (f32, f32, f32) {
+    fn reverse_gamma(v: f32) -> f32 {
+        if v <= 0.003_130_8 { 12.92 * v } else { 1.055 * v.powf(1.0 / 2.4) - 0.055 }
+    }
+    if y == 0.0 {
+        return (0.0, 0.0, 0.0);
+    }
+    let (cx, cy, cz) = (x / y, 1.0, (1.0 - x - y) / y);
+
+    let r = cx * 1.656_492 - cy * 0.354_851 - cz * 0.255_038;
+    let g = -cx * 0.707_196 + cy * 1.655_397 + cz * 0.036_152;
+    let b = cx * 0.051_713 - cy * 0.121_364 + cz * 1.011_53;
+
+    let (r, g, b) = (reverse_gamma(r.max(0.0)), reverse_gamma(g.max(0.0)), reverse_gamma(b.max(0.0)));
+    let max = r.max(g).max(b);
+    if max == 0.0 {
+        (0.0, 0.0, 0.0)
+    } else {
+        (r / max, g / max, b / max)
+    }
+}
+
+/// Converts a hue in degrees and a saturation between 0 and 1 to RGB at full value
+fn hsv_to_rgb(hue: f32, sat: f32) -> (f32, f32, f32) {
+    let h = (hue % 360.0) / 60.0;
+    let f = h - h.floor();
+    let (p, q, t) = (1.0 - sat, 1.0 - sat * f, 1.0 - sat * (1.0 - f));
+    match h.floor() as u8 {
+        0 => (1.0, t, p),
+        1 => (q, 1.0, p),
+        2 => (p, 1.0, t),
+        3 => (p, q, 1.0),
+        4 => (t, p, 1.0),
+        _ => (1.0, p, q),
+    }
+}
+
+/// Converts RGB values between 0 and 1 to a hue in degrees and a saturation between 0 and 1
+fn rgb_to_hs(r: f32, g: f32, b: f32) -> (f32, f32) {
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+    if delta == 0.0 {
+        return (0.0, 0.0);
+    }
+
+    let hue = if max == r {
+        60.0 * ((g - b) / delta)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    (if hue < 0.0 { hue + 360.0 } else { hue }, delta / max)
+}
+
+#[test]
+fn hs_xy_round_trip() {
+    for &(hue, sat) in &[(0, 254), (21845, 254), (43690, 254), (10923, 254), (54613, 127)] {
+        let (h, s) = xy_to_hs(hs_to_xy(hue, sat));
+        assert!((i32::from(h) - i32::from(hue)).abs() <= 200, "hue {} became {}", hue, h);
+        assert!((i32::from(s) - i32::from(sat)).abs() <= 2, "sat {} became {}", sat, s);
+    }
+
+    let xy = hs_to_xy(0, 254);
+    assert!((xy.x - 0.7006).abs() < 0.001 && (xy.y - 0.2993).abs() < 0.001);
+}
+
+#[test]
+fn closest_point_in_each_gamut() {
+    fn close(a: Xy, b: Xy) -> bool {
+        (a.x - b.x).abs() < 1e-5 && (a.y - b.y).abs() < 1e-5
+    }
+
+    for &gamut in &[GAMUT_A, GAMUT_B, GAMUT_C] {
+        let inside = Xy {
+            x: (gamut.red.x + gamut.green.x + gamut.blue.x) / 3.0,
+            y: (gamut.red.y + gamut.green.y + gamut.blue.y) / 3.0,
+        };
+        assert!(close(closest_point_in_gamut(inside, gamut), inside));
+
+        let edge = Xy { x: (gamut.red.x + gamut.green.x) / 2.0, y: (gamut.red.y + gamut.green.y) / 2.0 };
+        assert!(close(closest_point_in_gamut(edge, gamut), edge));
+
+        let beyond_red = Xy { x: gamut.red.x + 0.05, y: gamut.red.y - 0.02 };
+        assert!(close(closest_point_in_gamut(beyond_red, gamut), gamut.red));
+
+        let outside = Xy { x: 0.05, y: 0.9 };
+        let snapped = closest_point_in_gamut(outside, gamut);
+        let distance = |p: Xy| ((p.x - outside.x).powi(2) + (p.y - outside.y).powi(2)).sqrt();
+        assert!(!close(snapped, outside));
+        assert!(distance(snapped) <= distance(gamut.red));
+        assert!(distance(snapped) <= distance(gamut.green));
+        assert!(distance(snapped) <= distance(gamut.blue));
+    }
+}
+
+#[test]
+fn gamut_of_models() {
+    assert_eq!(gamut_for_model("LCT001"), Some(GAMUT_B));
+    assert_eq!(gamut_for_model("LST001"), Some(GAMUT_A));
+    assert_eq!(gamut_for_model("LCT015"), Some(GAMUT_C));
+    assert_eq!(gamut_for_model("LWB010"), None);
+
+    // Pure green is outside gamut B, so it's moved onto its edge
+    let green = rgb_to_xy_in_gamut((0, 255, 0), GAMUT_B);
+    assert!(GAMUT_B.contains(green));
+    assert!(!GAMUT_B.contains(rgb_to_xy(0.0, 1.0, 0.0)));
+}
+
+#[test]
+fn mired_kelvin() {
+    assert_eq!(mired_to_kelvin(153), 6536);
+    assert_eq!(mired_to_kelvin(500), 2000);
+    assert_eq!(mired_to_kelvin(366), 2732);
+    assert_eq!(kelvin_to_mired(2700), 370);
+    assert_eq!(mired_to_kelvin(0), 0);
+    assert_eq!(mired_to_kelvin(1), u16::MAX);
+}
@@ -1,6 +1,10 @@
+// error_chain 0.11 implements the deprecated `description()` and `cause()` of `Error`
+#![allow(deprecated)]
+
 use hyper;
-use std::convert::From;
+use std::convert::{From, Infallible};
 use serde_json;
+use std::error::Error as StdError;
 use std::io;
 
 impl From<crate::json::Error> for HueError {
@@ -14,6 +18,12 @@ impl From<crate::json::Error> for HueError {
     }
 }
 
+impl From<Infallible> for HueError {
+    fn from(e: Infallible) -> HueError {
+        match e {}
+    }
+}
+
 error_chain! {
     types {
         HueError, HueErrorKind, ResultExt, Result;
@@ -29,15 +39,131 @@ error_chain! {
             description("bridge error")
             display("Bridge error {:?} on {}: {}", error, address, description)
         }
+        /// The username is not (or no longer) registered on the bridge
+        Unauthorized {
+            description("unauthorized user")
+            display("The username is not authorized on the bridge, a new user has to be registered")
+        }
+        /// The bridge could not be reached
+        Unreachable {
+            description("bridge unreachable")
+            display("The bridge could not be reached")
+        }
+        /// The connection to the bridge could not be established, e.g. because its address
+        /// could not be resolved or the connection was refused
+        Connection {
+            description("connection error")
+            display("Could not connect to the bridge")
+        }
+        /// The bridge did not respond in time
+        Timeout {
+            description("timeout")
+            display("The request to the bridge timed out")
+        }
+        /// The response of the bridge could not be parsed
+        ///
+        /// The `serde_json::Error` describing why is the cause of this error.
+        DeserializeFailed {
+            body: String
+        } {
+            description("response could not be parsed")
+            display("Could not parse the response of the bridge: {}", body)
+        }
+        /// A command without any changes was about to be sent, which the bridge would reject
+        EmptyCommand {
+            description("empty command")
+            display("The command doesn't change anything")
+        }
+        /// Several errors that occured in the bridge from a single request
+        Multiple(errors: Vec<BridgeError>) {
+            description("multiple bridge errors")
+            display("Bridge errors: {:?}", errors)
+        }
     }
 
     foreign_links {
         JsonError(serde_json::Error) #[doc = "Json error"];
         HyperError(hyper::Error)     #[doc = "Hyper error"];
+        HttpError(hyper::http::Error) #[doc = "Error building an HTTP request, e.g. from an invalid URL"];
         IOError(io::Error)           #[doc = "IO error"];
     }
 }
 
+/// Turns a `ResourceNotAvailable` error from the bridge into `Ok(None)`
+pub(crate) fn not_found_as_none<T>(result: Result<T>) -> Result<Option<T>> {
+    match result {
+        Ok(t) => Ok(Some(t)),
+        Err(HueError(HueErrorKind::BridgeError{error: BridgeError::ResourceNotAvailable, ..}, _)) => Ok(None),
+        Err(e) => Err(e),
+    }
+}
+
+impl HueError {
+    /// Whether the username used is not authorized on the bridge
+    ///
+    /// This is the signal that a new user has to be registered with `register_user()`.
+    pub fn is_unauthorized(&self) -> bool {
+        matches!(*self.kind(),
+                 HueErrorKind::Unauthorized |
+                 HueErrorKind::BridgeError{error: BridgeError::UnauthorizedUser, ..})
+    }
+    /// Whether the bridge could not be talked to at all, as opposed to it sending a bad response
+    pub fn is_connection_error(&self) -> bool {
+        matches!(*self.kind(), HueErrorKind::Connection | HueErrorKind::Timeout | HueErrorKind::Unreachable)
+    }
+}
+
+/// The kind of error an IO error caused by the network is classified as, if any
+fn network_error_kind(e: &io::Error) -> Option<HueErrorKind> {
+    match e.kind() {
+        io::ErrorKind::TimedOut => Some(HueErrorKind::Timeout),
+        io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset |
+        io::ErrorKind::ConnectionAborted | io::ErrorKind::NotConnected |
+        io::ErrorKind::AddrNotAvailable => Some(HueErrorKind::Connection),
+        _ => None,
+    }
+}
+
+/// Wraps hyper and IO errors caused by the network in `Connection` or `Timeout`
+///
+/// Every other error, including hyper errors about bad responses, is returned unchanged.
+pub(crate) fn classify_network_error(e: HueError) -> HueError {
+    let kind = match *e.kind() {
+        HueErrorKind::HyperError(ref h) => {
+            let io = StdError::source(h).and_then(|cause| cause.downcast_ref::<io::Error>());
+            match io.and_then(network_error_kind) {
+                Some(kind) => Some(kind),
+                None if h.is_connect() => Some(HueErrorKind::Connection),
+                None => None,
+            }
+        }
+        HueErrorKind::IOError(ref io) => network_error_kind(io),
+        _ => None,
+    };
+    match kind {
+        Some(kind) => HueError::with_chain(e, kind),
+        None => e,
+    }
+}
+
+/// Wraps errors caused by not being able to connect to the bridge in `Unreachable`
+pub(crate) fn connection_as_unreachable(e: HueError) -> HueError {
+    let unreachable = match *e.kind() {
+        HueErrorKind::Connection | HueErrorKind::Timeout => true,
+        HueErrorKind::HyperError(ref h) => h.is_connect(),
+        HueErrorKind::IOError(ref io) => matches!(io.kind(),
+            io::ErrorKind::ConnectionRefused | io::ErrorKind::ConnectionReset |
+            io::ErrorKind::ConnectionAborted | io::ErrorKind::NotConnected |
+            io::ErrorKind::TimedOut),
+        _ => false,
+    };
+    if unreachable {
+        HueError::with_chain(e, HueErrorKind::Unreachable)
+    } else {
+        e
+    }
+}
+
 macro_rules! error_enum {
     (
         $(#[$meta:meta])*
@@ -112,6 +238,16 @@ error_enum!{
     }
 }
 
+impl BridgeError {
+    /// Whether the same request might succeed when retried
+    ///
+    /// Only `InternalError` (901) and `DeviceIsUnreachable` (304) are considered transient:
+    /// the bridge being busy and a light being briefly out of range of the mesh.
+    pub fn is_transient(&self) -> bool {
+        matches!(*self, BridgeError::InternalError | BridgeError::DeviceIsUnreachable)
+    }
+}
+
 #[test]
 fn bridge_errors() {
     use self::BridgeError::*;
@@ -123,3 +259,79 @@ fn bridge_errors() {
     assert_eq!(SceneCouldNotBeRemoved as u16, 403);
     assert_eq!(InternalError as u16, 901);
 }
+
+#[test]
+fn resource_not_available_as_none() {
+    let not_found: Result<()> = Err(crate::json::Error {
+        address: "/lights/999".to_owned(),
+        description: "resource, /lights/999, not available".to_owned(),
+        code: 3,
+    }.into());
+    assert!(not_found_as_none(not_found).unwrap().is_none());
+
+    let unauthorized: Result<()> = Err(crate::json::Error {
+        address: "/".to_owned(),
+        description: "unauthorized user".to_owned(),
+        code: 1,
+    }.into());
+    assert!(not_found_as_none(unauthorized).is_err());
+    assert_eq!(not_found_as_none(Ok(5)).unwrap(), Some(5));
+}
+
+#[test]
+fn connection_refused_is_unreachable() {
+    let refused = HueError::from(io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused"));
+    match connection_as_unreachable(refused) {
+        HueError(HueErrorKind::Unreachable, _) => (),
+        e => panic!("expected unreachable, got {:?}", e),
+    }
+
+    let other = HueError::from(io::Error::new(io::ErrorKind::InvalidData, "invalid data"));
+    match connection_as_unreachable(other) {
+        HueError(HueErrorKind::IOError(_), _) => (),
+        e => panic!("expected io error, got {:?}", e),
+    }
+}
+
+#[test]
+fn unauthorized_user() {
+    let unauthorized = HueError::from(crate::json::Error {
+        address: "/lights".to_owned(),
+        description: "unauthorized user".to_owned(),
+        code: 1,
+    });
+    assert!(unauthorized.is_unauthorized());
+    assert!(HueError::from(HueErrorKind::Unauthorized).is_unauthorized());
+    assert!(!HueError::from(HueErrorKind::Unreachable).is_unauthorized());
+}
+
+#[test]
+fn network_errors_are_classified() {
+    let refused = HueError::from(io::Error::new(io::ErrorKind::ConnectionRefused, "connection refused"));
+    match classify_network_error(refused) {
+        ref e @ HueError(HueErrorKind::Connection, _) => assert!(e.is_connection_error()),
+        e => panic!("expected connection error, got {:?}", e),
+    }
+
+    let timed_out = HueError::from(io::Error::new(io::ErrorKind::TimedOut, "timed out"));
+    match classify_network_error(timed_out) {
+        ref e @ HueError(HueErrorKind::Timeout, _) => assert!(e.is_connection_error()),
+        e => panic!("expected timeout, got {:?}", e),
+    }
+
+    let invalid = HueError::from(io::Error::new(io::ErrorKind::InvalidData, "invalid data"));
+    match classify_network_error(invalid) {
+        ref e @ HueError(HueErrorKind::IOError(_), _) => assert!(!e.is_connection_error()),
+        e => panic!("expected io error, got {:?}", e),
+    }
+}
+
+#[test]
+fn transient_bridge_errors() {
+    assert!(BridgeError::from(901).is_transient());
+    assert!(BridgeError::from(304).is_transient());
+    assert!(!BridgeError::from(1).is_transient());
+    assert!(!BridgeError::from(7).is_transient());
+    assert!(!BridgeError::from(201).is_transient());
+    assert!(!BridgeError::Other.is_transient());
+}
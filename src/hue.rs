@@ -1,13 +1,60 @@
-use serde::de::{Deserialize, Deserializer};
+use serde::de::{Deserialize, DeserializeOwned, Deserializer};
+use serde::ser::{Serialize, Serializer};
 
+use std::convert::{TryFrom, TryInto};
+use std::ops::Deref;
+use std::time::Duration;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use crate::color;
+
+
+/// The x and y coordinates of a colour in [CIE space](http://www.developers.meethue.com/documentation/core-concepts#color_gets_more_complicated)
+///
+/// Sent and received as a `[x, y]` array like the bridge expects. The coordinates are rounded
+/// to 4 decimals when sent, like the official apps do.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Xy {
+    /// The x coordinate, from 0 to 1
+    pub x: f32,
+    /// The y coordinate, from 0 to 1
+    pub y: f32,
+}
+
+impl From<(f32, f32)> for Xy {
+    fn from((x, y): (f32, f32)) -> Self {
+        Xy { x, y }
+    }
+}
+
+impl From<Xy> for (f32, f32) {
+    fn from(xy: Xy) -> Self {
+        (xy.x, xy.y)
+    }
+}
+
+impl Serialize for Xy {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let round = |c: f32| (c * 10_000.0).round() / 10_000.0;
+        (round(self.x), round(self.y)).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for Xy {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <(f32, f32)>::deserialize(deserializer).map(Xy::from)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 /// The state of the light with similar structure to `LightCommand`
 pub struct LightState {
     /// Whether the light is on
     pub on: bool,
     /// Brightness of the light. This is a scale from the minimum capable brightness, 1, to the maximum, 254.
-    pub bri: u8,
+    ///
+    /// Devices that can only be switched on and off, like smart plugs, don't have a brightness.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub bri: Option<u8>,
     /// Hue of the light. Both 0 and 65535 are red, 25500 is green and 46920 is blue.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub hue: Option<u16>,
@@ -16,7 +63,7 @@ pub struct LightState {
     pub sat: Option<u8>,
     /// The x and y coordinates of a colour in [CIE space](http://www.developers.meethue.com/documentation/core-concepts#color_gets_more_complicated)
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub xy: Option<(f32, f32)>,
+    pub xy: Option<Xy>,
     /// The [mired](http://en.wikipedia.org/wiki/Mired) colour temperature of the light.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ct: Option<u16>,
@@ -34,10 +81,225 @@ pub struct LightState {
     pub reachable: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The ID of a light, checked to be one the bridge could have given out
+///
+/// Lights are numbered from 1. The `Bridge` methods taking a light accept a `LightId` as
+/// well as a plain number or string, which is checked the same way, see `IntoId`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct LightId(usize);
+
+impl TryFrom<usize> for LightId {
+    type Error = HueError;
+
+    fn try_from(id: usize) -> Result<Self, HueError> {
+        if id == 0 {
+            Err("Invalid light ID: 0".into())
+        } else {
+            Ok(LightId(id))
+        }
+    }
+}
+
+impl<'a> TryFrom<&'a str> for LightId {
+    type Error = HueError;
+
+    fn try_from(id: &'a str) -> Result<Self, HueError> {
+        parse_id(id).ok_or_else(|| format!("Invalid light ID: {:?}", id).into()).and_then(LightId::try_from)
+    }
+}
+
+impl From<LightId> for usize {
+    fn from(id: LightId) -> usize {
+        id.0
+    }
+}
+
+impl Display for LightId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The ID of a group
+///
+/// Group 0 is the special group of all lights, see `bridge::ALL_LIGHTS_GROUP`. The `Bridge`
+/// methods taking a group accept a `GroupId` as well as a plain number or string, see `IntoId`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GroupId(usize);
+
+impl From<usize> for GroupId {
+    fn from(id: usize) -> Self {
+        GroupId(id)
+    }
+}
+
+impl<'a> TryFrom<&'a str> for GroupId {
+    type Error = HueError;
+
+    fn try_from(id: &'a str) -> Result<Self, HueError> {
+        parse_id(id).map(GroupId).ok_or_else(|| format!("Invalid group ID: {:?}", id).into())
+    }
+}
+
+impl From<GroupId> for usize {
+    fn from(id: GroupId) -> usize {
+        id.0
+    }
+}
+
+impl Display for GroupId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// The ID of a scene, checked to consist of up to 16 letters, digits and dashes
+///
+/// The `Bridge` methods taking a scene accept a `SceneId` as well as a string, see `IntoId`.
+/// It dereferences to `str` for everything else taking a scene ID, like `SceneRecall::new()`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SceneId(String);
+
+impl<'a> TryFrom<&'a str> for SceneId {
+    type Error = HueError;
+
+    fn try_from(id: &'a str) -> Result<Self, HueError> {
+        let valid = !id.is_empty() && id.len() <= 16 &&
+            id.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'-');
+        if valid {
+            Ok(SceneId(id.to_owned()))
+        } else {
+            Err(format!("Invalid scene ID: {:?}", id).into())
+        }
+    }
+}
+
+impl Deref for SceneId {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        &self.0
+    }
+}
+
+impl Display for SceneId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+/// Anything that can be passed as the ID of type `I` to the `Bridge` methods
+///
+/// Implemented for everything `I` can be made from with `TryFrom`, so a light can be given
+/// as a `LightId`, a `usize` or a `&str`, a group as a `GroupId`, a `usize` or a `&str` and a
+/// scene as a `SceneId` or a `&str`. Invalid IDs fail before anything is sent to the bridge.
+pub trait IntoId<I> {
+    /// Converts to the ID, failing if it isn't a valid one
+    fn into_id(self) -> Result<I, HueError>;
+}
+
+impl<I, T: TryInto<I>> IntoId<I> for T where HueError: From<T::Error> {
+    fn into_id(self) -> Result<I, HueError> {
+        Ok(self.try_into()?)
+    }
+}
+
+/// Parses an ID the way the bridge writes them, only digits without leading zeros
+fn parse_id(id: &str) -> Option<usize> {
+    if id.is_empty() || (id.len() > 1 && id.starts_with('0')) || !id.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    id.parse().ok()
+}
+
+/// What colour a light currently shows, depending on what it supports
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ColorSummary {
+    /// The light has no colour settings, it can only be switched on and off or dimmed
+    OnOff,
+    /// A white light with the given mired colour temperature
+    Temperature(u16),
+    /// A colour given by hue and saturation
+    Color {
+        /// The hue, see `LightState::hue`
+        hue: u16,
+        /// The saturation, see `LightState::sat`
+        sat: u8,
+    },
+    /// A colour given by CIE coordinates
+    ColorXy(Xy),
+}
+
+impl LightState {
+    /// The hue of the light, or `default` if it doesn't support colours
+    pub fn hue_or(&self, default: u16) -> u16 {
+        self.hue.unwrap_or(default)
+    }
+    /// The colour temperature of the light in kelvin, if it supports colour temperatures
+    pub fn kelvin(&self) -> Option<u16> {
+        self.ct.map(color::mired_to_kelvin)
+    }
+    /// Whether the light supports colours and not just white
+    pub fn is_color_capable(&self) -> bool {
+        self.hue.is_some() || self.xy.is_some()
+    }
+    /// Summarizes the colour of the light
+    ///
+    /// The `colormode` decides which of the settings the light reports is the active one.
+    pub fn color_summary(&self) -> ColorSummary {
+        let hs = match (self.hue, self.sat) {
+            (Some(hue), Some(sat)) => Some(ColorSummary::Color { hue, sat }),
+            _ => None,
+        };
+        let xy = self.xy.map(ColorSummary::ColorXy);
+        let ct = self.ct.map(ColorSummary::Temperature);
+        let active = match self.colormode.as_deref() {
+            Some("hs") => hs,
+            Some("xy") => xy,
+            Some("ct") => ct,
+            _ => None,
+        };
+        active.or(xy).or(hs).or(ct).unwrap_or(ColorSummary::OnOff)
+    }
+    /// Whether both states look the same, ignoring the small jitter lights report
+    ///
+    /// `bri` and `sat` may differ by up to `bri_tol` and the x and y coordinates by up to
+    /// `xy_tol`. `hue` and `ct` may differ by `bri_tol` scaled from the 254 steps of `bri` to
+    /// their own range, so 65535 / 254 (about 258) per step for `hue` and 347 / 254 (about 1.4)
+    /// mired per step for `ct`, rounded down. `hue` wraps around, so 65535 is close to 0.
+    /// `on` has to be equal.
+    pub fn approx_eq(&self, other: &LightState, bri_tol: u8, xy_tol: f32) -> bool {
+        fn within<T: Into<i64>>(a: Option<T>, b: Option<T>, tol: i64, wrap: Option<i64>) -> bool {
+            match (a, b) {
+                (Some(a), Some(b)) => {
+                    let diff = (a.into() - b.into()).abs();
+                    wrap.map_or(diff, |wrap| diff.min(wrap - diff)) <= tol
+                }
+                (None, None) => true,
+                _ => false,
+            }
+        }
+        let hue_tol = i64::from(bri_tol) * 65535 / 254;
+        let ct_tol = i64::from(bri_tol) * (500 - 153) / 254;
+        let xy_close = match (self.xy, other.xy) {
+            (Some(a), Some(b)) => (a.x - b.x).abs() <= xy_tol && (a.y - b.y).abs() <= xy_tol,
+            (a, b) => a == b,
+        };
+        self.on == other.on && xy_close &&
+            within(self.bri, other.bri, bri_tol.into(), None) &&
+            within(self.sat, other.sat, bri_tol.into(), None) &&
+            within(self.hue, other.hue, hue_tol, Some(65536)) &&
+            within(self.ct, other.ct, ct_tol, None)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 /// The state of the light. Same as `LightState` except there's no `reachable` field.
 pub struct LightStateChange {
     /// Whether the light is on
+    ///
+    /// In a scene, `Some(false)` turns the light off when the scene is recalled, while `None`
+    /// leaves whether the light is on unmanaged by the scene.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub on: Option<bool>,
     /// Brightness of the light. This is a scale from the minimum capable brightness, 1, to the maximum, 254.
@@ -51,7 +313,7 @@ pub struct LightStateChange {
     pub sat: Option<u8>,
     /// The x and y coordinates of a colour in [CIE space](http://www.developers.meethue.com/documentation/core-concepts#color_gets_more_complicated)
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub xy: Option<(f32, f32)>,
+    pub xy: Option<Xy>,
     /// The [mired](http://en.wikipedia.org/wiki/Mired) colour temperature of the light.
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ct: Option<u16>,
@@ -68,6 +330,169 @@ pub struct LightStateChange {
     pub colormode: Option<String>,
 }
 
+impl<'a> From<&'a LightState> for LightStateChange {
+    /// Copies everything but `reachable`
+    fn from(state: &'a LightState) -> Self {
+        LightStateChange {
+            on: Some(state.on),
+            bri: state.bri,
+            hue: state.hue,
+            sat: state.sat,
+            xy: state.xy,
+            ct: state.ct,
+            alert: Some(state.alert.clone()),
+            effect: state.effect.clone(),
+            colormode: state.colormode.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Software update state of a light
+pub struct LightSwUpdate {
+    /// Whether an update is available or being installed
+    pub state: UpdateState,
+    /// Time of the last software update of the light
+    pub lastinstall: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// The range of colour temperatures a light can show, in mireds
+pub struct CtRange {
+    /// The warmest colour temperature
+    pub min: u16,
+    /// The coldest colour temperature
+    pub max: u16,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+/// What a light is able to show, as reported by the light itself
+pub struct LightControl {
+    /// The lowest brightness the light can be dimmed to, in 1/1000ths of its maximum
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub mindimlevel: Option<u16>,
+    /// The light output at full brightness in lumen
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub maxlumen: Option<u16>,
+    /// The type of the gamut of the light: "A", "B", "C" or "other"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colorgamuttype: Option<String>,
+    /// The x and y coordinates of the red, green and blue corners of the gamut of the light
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colorgamut: Option<Vec<Xy>>,
+    /// The range of colour temperatures of the light
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub ct: Option<CtRange>,
+}
+
+impl LightControl {
+    /// The gamut of colours the light reports, or `None` for lights without colours
+    pub fn gamut(&self) -> Option<color::Gamut> {
+        match self.colorgamut.as_deref() {
+            Some(&[red, green, blue]) => Some(color::Gamut { red, green, blue }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// The capabilities of a light, reported by current bridges
+pub struct LightCapabilitiesInfo {
+    /// Whether the light is certified by Philips
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub certified: Option<bool>,
+    /// What the light is able to show
+    #[serde(default)]
+    pub control: LightControl,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Configuration of a light as set in the Hue app
+pub struct LightConfig {
+    /// The archetype of the light, which decides its icon (e.g. "sultanbulb")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archetype: Option<String>,
+    /// What the light is used for, e.g. "functional", "decorative" or "mixed"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    /// The direction the light shines in, e.g. "omnidirectional", "upwards" or "downwards"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<String>,
+}
+
+impl Light {
+    /// The gamut of colours the light can show, or `None` for unknown and white-only models
+    ///
+    /// The gamut the light reports in its `capabilities` is used if there is one, otherwise
+    /// it's looked up by the model of the light.
+    pub fn gamut(&self) -> Option<color::Gamut> {
+        self.capabilities.as_ref()
+            .and_then(|c| c.control.gamut())
+            .or_else(|| color::gamut_for_model(&self.modelid))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// When the bridge last searched for new lights, as reported in `lastscan`
+pub enum ScanState {
+    /// No search has been done since the bridge was started
+    NoScan,
+    /// The bridge is searching right now
+    Active,
+    /// The time in UTC the last search was done at (e.g. "2017-06-29T12:02:22")
+    Done(String),
+}
+
+impl ScanState {
+    /// The state as sent by the bridge: "none", "active" or the time of the last search
+    pub fn as_str(&self) -> &str {
+        match *self {
+            ScanState::NoScan => "none",
+            ScanState::Active => "active",
+            ScanState::Done(ref time) => time,
+        }
+    }
+    /// The time the last search was done at, or `None` if no search is done or it can't be parsed
+    #[cfg(feature = "chrono")]
+    pub fn timestamp(&self) -> Option<chrono::DateTime<chrono::Utc>> {
+        use chrono::TimeZone;
+
+        match *self {
+            ScanState::Done(ref time) => chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%dT%H:%M:%S")
+                .ok()
+                .map(|t| chrono::Utc.from_utc_datetime(&t)),
+            _ => None,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for ScanState {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let state = String::deserialize(deserializer)?;
+        Ok(match &*state {
+            "none" => ScanState::NoScan,
+            "active" => ScanState::Active,
+            _ => ScanState::Done(state),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
+/// Changes to the configuration of a light
+///
+/// This is passed to `bridge::set_light_config()`
+pub struct LightConfigModifier {
+    /// The archetype of the light, which decides its icon (e.g. "sultanbulb")
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub archetype: Option<String>,
+    /// What the light is used for, e.g. "functional", "decorative" or "mixed"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub function: Option<String>,
+    /// The direction the light shines in, e.g. "omnidirectional", "upwards" or "downwards"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<String>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 /// Details about a specific light
 pub struct Light {
@@ -80,13 +505,63 @@ pub struct Light {
     /// Unique ID of the device
     pub uniqueid: String,
     /// The state of the light (See `LightState` for more)
-    pub state: LightState
+    pub state: LightState,
+    /// The type of the light, e.g. "Extended color light"
+    #[serde(rename="type")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub light_type: Option<String>,
+    /// The manufacturer of the light
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub manufacturername: Option<String>,
+    /// The name of the product, e.g. "Hue color lamp"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub productname: Option<String>,
+    /// ID of the product, unique per manufacturer
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub productid: Option<String>,
+    /// Configuration of the light as set in the Hue app
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub config: Option<LightConfig>,
+    /// Software update state of the light
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub swupdate: Option<LightSwUpdate>,
+    /// What the light is able to show, reported by current bridges
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub capabilities: Option<LightCapabilitiesInfo>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// An [alert effect](http://www.developers.meethue.com/documentation/core-concepts#some_extra_fun_stuff)
+/// making a light flash to draw attention to it
+pub enum Alert {
+    /// Stops any ongoing alert
+    None,
+    /// Makes the light breathe once
+    Select,
+    /// Makes the light breathe for 15 seconds
+    LSelect,
+}
+
+impl Alert {
+    /// Parses the name the bridge uses for an alert, `None` if it is unknown
+    fn from_name(name: &str) -> Option<Alert> {
+        match name {
+            "none" => Some(Alert::None),
+            "select" => Some(Alert::Select),
+            "lselect" => Some(Alert::LSelect),
+            _ => None,
+        }
+    }
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+#[derive(Debug, Default, Clone, PartialEq, Serialize, Deserialize)]
 /// Struct for building a command that will be sent to the Hue bridge telling it what to do with a light
 ///
 /// View [the lights-api documention](http://www.developers.meethue.com/documentation/lights-api) for more information
+///
+/// Commands compared with `==` must have exactly the same `xy` coordinates, use `diff_from()`
+/// to compare a command with the state of a light within a tolerance.
 pub struct LightCommand {
     /// Whether to turn the light off or on
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -102,13 +577,13 @@ pub struct LightCommand {
     pub sat: Option<u8>,
     /// The x and y coordinates of a colour in [CIE space](http://www.developers.meethue.com/documentation/core-concepts#color_gets_more_complicated)
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub xy: Option<(f32, f32)>,
+    pub xy: Option<Xy>,
     /// The Mired Color temperature of the light. 2012 connected lights are capable of 153 (6500K) to 500 (2000K).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub ct: Option<u16>,
     /// The [alert effect](http://www.developers.meethue.com/documentation/core-concepts#some_extra_fun_stuff)
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub alert: Option<String>,
+    pub alert: Option<Alert>,
     /// The dynamic effect of the light. It can be either "none" or "colorloop"
     ///
     /// If "colorloop", the light will cycle hues
@@ -134,7 +609,77 @@ pub struct LightCommand {
     pub xy_inc: Option<(i16, i16)>,
 }
 
+impl<'a> From<&'a LightStateChange> for LightCommand {
+    /// Copies the fields the two have in common, `colormode` is ignored
+    fn from(state: &'a LightStateChange) -> Self {
+        LightCommand {
+            on: state.on,
+            bri: state.bri,
+            hue: state.hue,
+            sat: state.sat,
+            xy: state.xy,
+            ct: state.ct,
+            alert: state.alert.as_ref().and_then(|a| Alert::from_name(a)),
+            effect: state.effect.clone(),
+            ..Default::default()
+        }
+    }
+}
+
 impl LightCommand {
+    /// Removes the colour settings (`hue`, `sat`, `xy` and their increments), keeping `ct`
+    ///
+    /// White lights reject commands with colours, so send them this version of a command
+    /// meant for all lights when `LightState::is_color_capable()` is false.
+    pub fn white_only(self) -> Self {
+        LightCommand {
+            hue: None,
+            sat: None,
+            xy: None,
+            hue_inc: None,
+            sat_inc: None,
+            xy_inc: None,
+            ..self
+        }
+    }
+    /// Whether the command doesn't change anything, which makes the bridge reject it
+    ///
+    /// A transition time alone doesn't change anything, so it's ignored.
+    pub fn is_empty(&self) -> bool {
+        LightCommand { transitiontime: None, ..self.clone() } == LightCommand::default()
+    }
+    /// Turns the light on to a warm white (2200K) at full brightness
+    pub fn warm_white() -> Self {
+        LightCommand::default().on().with_ct(454).with_bri(254)
+    }
+    /// Turns the light on to a cool white (6500K) at full brightness
+    pub fn cool_white() -> Self {
+        LightCommand::default().on().with_ct(153).with_bri(254)
+    }
+    /// Turns the light on to the "Relax" preset of the Hue app
+    pub fn relax() -> Self {
+        LightCommand::default().on().with_ct(447).with_bri(144)
+    }
+    /// Turns the light on to the "Concentrate" preset of the Hue app
+    pub fn concentrate() -> Self {
+        LightCommand::default().on().with_ct(233).with_bri(254)
+    }
+    /// Turns the light on to a saturated red at full brightness
+    pub fn red() -> Self {
+        LightCommand::saturated_colour(0)
+    }
+    /// Turns the light on to a saturated green at full brightness
+    pub fn green() -> Self {
+        LightCommand::saturated_colour(21845)
+    }
+    /// Turns the light on to a saturated blue at full brightness
+    pub fn blue() -> Self {
+        LightCommand::saturated_colour(43690)
+    }
+    fn saturated_colour(hue: u16) -> Self {
+        let xy = color::closest_point_in_gamut(color::hs_to_xy(hue, 254), color::GAMUT_C);
+        LightCommand::default().on().with_xy(xy).with_bri(254)
+    }
     /// Returns a `LightCommand` that turns a light on
     pub fn on(self) -> Self {
         LightCommand { on: Some(true), ..self }
@@ -144,6 +689,11 @@ impl LightCommand {
         LightCommand { on: Some(false), ..self }
     }
     /// Sets the brightness to set the light to
+    ///
+    /// This doesn't turn the light on. Lights that are on change their brightness and lights
+    /// that are off stay off: the bridge rejects the brightness for them with
+    /// `BridgeError::DeviceIsSetToOff`, and they come back on with their previous brightness.
+    /// Add `on()` to turn them on at this brightness.
     pub fn with_bri(self, b: u8) -> Self {
         LightCommand { bri: Some(b), ..self }
     }
@@ -156,15 +706,15 @@ impl LightCommand {
         LightCommand { sat: Some(s), ..self }
     }
     /// Sets the xy colour coordinates to set the light to
-    pub fn with_xy(self, xy: (f32, f32)) -> Self {
-        LightCommand { xy: Some(xy), ..self }
+    pub fn with_xy<T: Into<Xy>>(self, xy: T) -> Self {
+        LightCommand { xy: Some(xy.into()), ..self }
     }
     /// Sets the temperature to set the light to
     pub fn with_ct(self, c: u16) -> Self {
         LightCommand { ct: Some(c), ..self }
     }
     /// Sets the alert mode to set the light to
-    pub fn with_alert(self, a: String) -> Self {
+    pub fn with_alert(self, a: Alert) -> Self {
         LightCommand { alert: Some(a), ..self }
     }
     /// Sets the effect mode to set the light to
@@ -175,6 +725,10 @@ impl LightCommand {
     pub fn with_transitiontime(self, a: u16) -> Self {
         LightCommand { transitiontime: Some(a), ..self }
     }
+    /// Sets the transition time from a `Duration`, rounded to the closest 100ms
+    pub fn with_transition(self, duration: Duration) -> Self {
+        self.with_transitiontime(deciseconds(duration))
+    }
     /// Sets the brightness increment value
     pub fn with_bri_inc(self, b: i16) -> Self {
         LightCommand { bri_inc: Some(b), ..self }
@@ -195,9 +749,79 @@ impl LightCommand {
     pub fn with_xy_inc(self, xy: (i16, i16)) -> Self {
         LightCommand { xy_inc: Some(xy), ..self }
     }
+    /// Combines two commands. Fields set in `other` take precedence over the ones in `self`.
+    pub fn merge(self, other: LightCommand) -> Self {
+        LightCommand {
+            on: other.on.or(self.on),
+            bri: other.bri.or(self.bri),
+            hue: other.hue.or(self.hue),
+            sat: other.sat.or(self.sat),
+            xy: other.xy.or(self.xy),
+            ct: other.ct.or(self.ct),
+            alert: other.alert.or(self.alert),
+            effect: other.effect.or(self.effect),
+            transitiontime: other.transitiontime.or(self.transitiontime),
+            bri_inc: other.bri_inc.or(self.bri_inc),
+            sat_inc: other.sat_inc.or(self.sat_inc),
+            hue_inc: other.hue_inc.or(self.hue_inc),
+            ct_inc: other.ct_inc.or(self.ct_inc),
+            xy_inc: other.xy_inc.or(self.xy_inc),
+        }
+    }
+    /// Returns a command containing only the fields that differ from the given state
+    ///
+    /// The `xy` coordinates are compared with a small tolerance. `alert` and the
+    /// increments are relative to the current state and therefore always kept.
+    /// `transitiontime` is only kept if anything else is left to send.
+    pub fn diff_from(&self, state: &LightState) -> LightCommand {
+        fn differs<T: PartialEq>(new: &Option<T>, old: Option<&T>) -> bool {
+            new.as_ref().map(|n| Some(n) != old).unwrap_or(false)
+        }
+
+        let mut cmd = self.clone();
+        if !differs(&cmd.on, Some(&state.on)) { cmd.on = None }
+        if !differs(&cmd.bri, state.bri.as_ref()) { cmd.bri = None }
+        if !differs(&cmd.hue, state.hue.as_ref()) { cmd.hue = None }
+        if !differs(&cmd.sat, state.sat.as_ref()) { cmd.sat = None }
+        if !differs(&cmd.ct, state.ct.as_ref()) { cmd.ct = None }
+        if !differs(&cmd.effect, state.effect.as_ref()) { cmd.effect = None }
+        if let (Some(xy), Some(state_xy)) = (cmd.xy, state.xy) {
+            if (xy.x - state_xy.x).abs() < XY_EPSILON && (xy.y - state_xy.y).abs() < XY_EPSILON {
+                cmd.xy = None
+            }
+        }
+
+        let nothing_left = cmd.on.is_none() && cmd.bri.is_none() && cmd.hue.is_none() &&
+            cmd.sat.is_none() && cmd.xy.is_none() && cmd.ct.is_none() && cmd.alert.is_none() &&
+            cmd.effect.is_none() && cmd.bri_inc.is_none() && cmd.sat_inc.is_none() &&
+            cmd.hue_inc.is_none() && cmd.ct_inc.is_none() && cmd.xy_inc.is_none();
+        if nothing_left {
+            cmd.transitiontime = None;
+        }
+        cmd
+    }
+    /// Whether the light is in the state this command asks for
+    ///
+    /// `alert`, `transitiontime` and the increments are not checked since they don't
+    /// describe a state the light ends up in.
+    pub fn took_effect(&self, state: &LightState) -> bool {
+        let cmd = self.diff_from(state);
+        cmd.on.is_none() && cmd.bri.is_none() && cmd.hue.is_none() && cmd.sat.is_none() &&
+            cmd.xy.is_none() && cmd.ct.is_none() && cmd.effect.is_none()
+    }
+}
+
+/// Converts a duration to the multiples of 100ms used for transition times, saturating at `u16::MAX`
+fn deciseconds(duration: Duration) -> u16 {
+    let rounded = (u64::from(duration.subsec_nanos()) + 50_000_000) / 100_000_000;
+    let ds = duration.as_secs().saturating_mul(10).saturating_add(rounded);
+    ds.min(u64::from(u16::MAX)) as u16
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+/// Largest difference in `xy` coordinates still considered the same colour
+const XY_EPSILON: f32 = 0.0005;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 /// Type of a group
 pub enum GroupType{
     /// Multisource luminaire group.
@@ -211,6 +835,9 @@ pub enum GroupType{
 }
 
 use std::fmt::{self, Display};
+use std::str::FromStr;
+
+use crate::errors::HueError;
 
 impl Display for GroupType {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
@@ -224,7 +851,22 @@ impl Display for GroupType {
     }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+impl FromStr for GroupType {
+    type Err = HueError;
+
+    fn from_str(s: &str) -> Result<Self, HueError> {
+        use self::GroupType::*;
+        Ok(match s {
+            "Luminaire" => Luminaire,
+            "LightSource" => LightSource,
+            "LightGroup" => LightGroup,
+            "Room" => Room,
+            _ => return Err(format!("Unknown group type: {:?}", s).into())
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[allow(missing_docs)]
 /// Class of the room of a group supported by the Hue API
 pub enum RoomClass{
@@ -279,12 +921,47 @@ impl Display for RoomClass {
     }
 }
 
+impl FromStr for RoomClass {
+    type Err = HueError;
+
+    fn from_str(s: &str) -> Result<Self, HueError> {
+        use self::RoomClass::*;
+        Ok(match s {
+            "Living room" => LivingRoom,
+            "Kitchen" => Kitchen,
+            "Dining" => Dining,
+            "Bedroom" => Bedroom,
+            "Kids bedroom" => KidsBedroom,
+            "Bathroom" => Bathroom,
+            "Nursery" => Nursery,
+            "Recreation" => Recreation,
+            "Office" => Office,
+            "Gym" => Gym,
+            "Hallway" => Hallway,
+            "Toilet" => Toilet,
+            "Front door" => FrontDoor,
+            "Garage" => Garage,
+            "Terrace" => Terrace,
+            "Garden" => Garden,
+            "Driveway" => Driveway,
+            "Carport" => Carport,
+            "Other" => Other,
+            _ => return Err(format!("Unknown room class: {:?}", s).into())
+        })
+    }
+}
+
 fn string_to_usize_vec<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<usize>, D::Error> {
-    <Vec<String>>::deserialize(deserializer).map(|v| v
+    use serde::de::Error;
+
+    <Vec<String>>::deserialize(deserializer)?
         .into_iter()
-        .map(|s| s.parse().unwrap())
+        .map(|s| s.parse().map_err(D::Error::custom))
         .collect()
-    )
+}
+
+fn usize_vec_to_string<S: Serializer>(v: &[usize], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(v.iter().map(|n| n.to_string()))
 }
 
 #[derive(Debug, Clone, Deserialize, Serialize)]
@@ -293,7 +970,7 @@ pub struct Group {
     /// Name of the group. (Default name is "Group").
     pub name: String,
     /// IDs of all the lights in this group
-    #[serde(deserialize_with = "string_to_usize_vec")]
+    #[serde(deserialize_with = "string_to_usize_vec", serialize_with = "usize_vec_to_string")]
     pub lights: Vec<usize>,
     #[serde(rename="type")]
     /// Type of the group
@@ -313,7 +990,33 @@ pub struct Group {
     pub class: Option<RoomClass>
 }
 
+impl Group {
+    /// The class of the group if it is a room, or `None` for other types of groups
+    pub fn room_class(&self) -> Option<RoomClass> {
+        if self.group_type == GroupType::Room { self.class } else { None }
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
+/// A group to be created using `create_group_from()`
+pub struct GroupCreator {
+    /// Name of the group.
+    pub name: String,
+    /// IDs of all the lights in the group.
+    #[serde(serialize_with = "usize_vec_to_string")]
+    pub lights: Vec<usize>,
+    /// Type of the group
+    #[serde(rename="type")]
+    pub group_type: GroupType,
+    /// The class of the room, if the type of the group is `Room`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub class: Option<RoomClass>,
+    /// Whether the bridge can just delete this group.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recycle: Option<bool>
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
 /// Attributes of a group to be changed using `set_group_attributes()`
 pub struct GroupCommand {
     /// The new name for the group.
@@ -344,13 +1047,38 @@ pub struct GroupState {
     pub lastswitched: Option<String>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Whether the lights of a group are on, derived from a `GroupState`
+pub enum GroupOnStatus {
+    /// All lights are on
+    AllOn,
+    /// All lights are off
+    AllOff,
+    /// Some lights are on and some are off
+    Mixed,
+}
+
+impl GroupState {
+    /// Whether all, none or only some of the lights in the group are on
+    pub fn status(&self) -> GroupOnStatus {
+        match (self.any_on, self.all_on) {
+            (true, true) => GroupOnStatus::AllOn,
+            (false, _) => GroupOnStatus::AllOff,
+            (true, false) => GroupOnStatus::Mixed,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Deserialize)]
 /// Responses from the `discover` function
 pub struct Discovery{
     /// The ID of the bridge
     pub id: String,
     /// The local IP address of the bridge
-    pub internalipaddress: String
+    pub internalipaddress: String,
+    /// The port of the bridge, if it doesn't listen on the standard port 80
+    #[serde(default)]
+    pub port: Option<u16>
 }
 
 impl Discovery {
@@ -367,22 +1095,33 @@ impl Discovery {
         let Discovery{internalipaddress, ..} = self;
         internalipaddress
     }
+    /// The address to connect to the bridge on, the IP followed by the port if it has one
+    pub fn address(&self) -> String{
+        match self.port {
+            Some(port) => format!("{}:{}", self.internalipaddress, port),
+            None => self.internalipaddress.clone(),
+        }
+    }
+    /// Whether this is the bridge with the given id, ignoring case
+    pub fn matches_id(&self, id: &str) -> bool{
+        self.id.eq_ignore_ascii_case(id)
+    }
 }
 
 pub use serde_json::{Map as JsonMap, Value as JsonValue};
 use std::collections::BTreeMap;
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// Contains information about what can be updated
 pub struct DeviceTypes {
     /// Whether there is an update available for the bridge.
-    bridge: bool,
+    pub bridge: bool,
     /// List of lights to be updated.
-    #[serde(deserialize_with = "string_to_usize_vec")]
-    lights: Vec<usize>,
+    #[serde(deserialize_with = "string_to_usize_vec", serialize_with = "usize_vec_to_string")]
+    pub lights: Vec<usize>,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// Information about software updates on the bridge
 pub struct SoftwareUpdate {
     /// Lets the bridge search for software updates
@@ -400,7 +1139,172 @@ pub struct SoftwareUpdate {
     pub notify: bool,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A ZigBee channel the bridge can use to talk to the lights
+pub enum ZigbeeChannel {
+    /// Channel 11
+    Ch11,
+    /// Channel 15
+    Ch15,
+    /// Channel 20
+    Ch20,
+    /// Channel 25
+    Ch25,
+    /// No channel picked yet, as on factory new bridges
+    Undefined,
+    /// A channel the Hue API doesn't document
+    Other(u8),
+}
+
+impl ZigbeeChannel {
+    /// The number of the channel, 0 if undefined
+    pub fn channel_number(&self) -> u8 {
+        match *self {
+            ZigbeeChannel::Ch11 => 11,
+            ZigbeeChannel::Ch15 => 15,
+            ZigbeeChannel::Ch20 => 20,
+            ZigbeeChannel::Ch25 => 25,
+            ZigbeeChannel::Undefined => 0,
+            ZigbeeChannel::Other(n) => n,
+        }
+    }
+}
+
+impl From<u8> for ZigbeeChannel {
+    fn from(n: u8) -> Self {
+        match n {
+            11 => ZigbeeChannel::Ch11,
+            15 => ZigbeeChannel::Ch15,
+            20 => ZigbeeChannel::Ch20,
+            25 => ZigbeeChannel::Ch25,
+            0 => ZigbeeChannel::Undefined,
+            n => ZigbeeChannel::Other(n),
+        }
+    }
+}
+
+impl Serialize for ZigbeeChannel {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.channel_number().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for ZigbeeChannel {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u8::deserialize(deserializer).map(ZigbeeChannel::from)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// The state of a software update, as reported by `swupdate2`
+pub enum UpdateState {
+    /// The state could not be determined
+    Unknown,
+    /// The device cannot be updated
+    NotUpdatable,
+    /// No updates are available
+    NoUpdates,
+    /// An update is being transferred to the device
+    Transferring,
+    /// An update is ready to be installed on the device
+    ReadyToInstall,
+    /// At least one device has an update ready to be installed
+    AnyReadyToInstall,
+    /// All devices have an update ready to be installed
+    AllReadyToInstall,
+    /// An update is being installed
+    Installing,
+    /// The update failed
+    Error,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Update state of the bridge itself
+pub struct BridgeUpdate {
+    /// Update state of the bridge
+    pub state: UpdateState,
+    /// Time of the last software update of the bridge
+    pub lastinstall: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Automatic installation of software updates
+pub struct AutoInstall {
+    /// Whether updates are installed automatically
+    pub on: bool,
+    /// The local time of day updates are installed at (e.g. "T14:00:00")
+    pub updatetime: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Information about software updates on current bridges
+pub struct SoftwareUpdate2 {
+    /// Lets the bridge search for software updates
+    pub checkforupdate: bool,
+    /// Overall update state of the bridge and its devices
+    pub state: UpdateState,
+    /// Time of the last change to the update state
+    pub lastchange: Option<String>,
+    /// Update state of the bridge itself
+    pub bridge: BridgeUpdate,
+    /// Automatic installation of software updates
+    pub autoinstall: AutoInstall,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+/// Whether the bridge can reach one of its internet services
+pub enum ConnectionStatus {
+    /// The service can be reached
+    Connected,
+    /// The service can't be reached
+    Disconnected,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Connectivity of the bridge to the internet services it uses
+pub struct InternetServices {
+    /// Connection to the internet
+    pub internet: ConnectionStatus,
+    /// Connection to the remote access portal
+    pub remoteaccess: ConnectionStatus,
+    /// Connection to the time server
+    pub time: ConnectionStatus,
+    /// Connection to the software update server
+    pub swupdate: ConnectionStatus,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// The state of a backup of the bridge, used when migrating to a new bridge
+pub enum BackupState {
+    /// No backup is being made or restored
+    #[serde(rename="idle")]
+    Idle,
+    /// A backup is being made for migrating to another bridge
+    #[serde(rename="startmigration")]
+    StartMigration,
+    /// The backup file is ready, and the bridge is disabled until it's restored elsewhere
+    #[serde(rename="fileready_disabled")]
+    FileReadyDisabled,
+    /// The bridge is preparing to restore a backup
+    #[serde(rename="prepare_restore")]
+    PrepareRestore,
+    /// A backup is being restored on the bridge
+    #[serde(rename="restoring")]
+    Restoring,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Status of backing up or restoring the bridge
+pub struct BackupStatus {
+    /// What the bridge is doing with its backup
+    pub status: BackupState,
+    /// The error of the last backup or restore, 0 if there was none
+    pub errorcode: i32,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// A user in the whitelist of a `Configuration`
 pub struct WhitelistUser {
     /// Name of the user. It's what you specify as `devicetype` when registering a user
@@ -413,13 +1317,19 @@ pub struct WhitelistUser {
     pub create_date: String,
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// Configuration of the bridge
 pub struct Configuration {
     /// Name of the bridge. This is also its uPnP name.
     pub name: String,
     /// Contains information about software updates
     pub swupdate: SoftwareUpdate,
+    /// Contains information about software updates on current bridges
+    pub swupdate2: Option<SoftwareUpdate2>,
+    /// Connectivity of the bridge to its internet services. Only reported by current bridges.
+    pub internetservices: Option<InternetServices>,
+    /// Status of backing up or restoring the bridge. Only reported by current bridges.
+    pub backup: Option<BackupStatus>,
     /// A list of all registered users
     pub whitelist: BTreeMap<String, WhitelistUser>,
     /// Version of the hue API on the bridge.
@@ -451,8 +1361,8 @@ pub struct Configuration {
     pub localtime: String,
     /// Timezone of the bridge as OlsenIDs (e.g. "Europe/Amsterdam") or "none".
     pub timezone: String,
-    /// The current wireless frequency channel used by the bridge. It can take values of 11, 15, 20,25 or 0 if undefined (factory new).
-    pub zigbeechannel: u8,
+    /// The current wireless frequency channel used by the bridge
+    pub zigbeechannel: ZigbeeChannel,
     /// This parameter uniquely identifies the hardware model of the bridge (BSB001, BSB002).
     pub modelid: String,
     /// The unique bridge id. This is currently generated from the bridge Ethernet MAC address.
@@ -463,11 +1373,67 @@ pub struct Configuration {
     pub replacesbridgeid: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+impl Configuration {
+    /// Gets the whitelist entry of the given username
+    pub fn whitelist_user(&self, username: &str) -> Option<&WhitelistUser> {
+        self.whitelist.get(username)
+    }
+    /// The users in the whitelist, the one that wasn't used for the longest time first
+    ///
+    /// Users with a last use date that can't be parsed come last.
+    pub fn whitelist_by_last_use(&self) -> Vec<(&String, &WhitelistUser)> {
+        let mut users: Vec<_> = self.whitelist.iter().collect();
+        users.sort_by_key(|&(_, user)| {
+            let date = date_key(&user.last_use_date);
+            (date.is_none(), date)
+        });
+        users
+    }
+}
+
+/// The date if it has the form the bridge sends (e.g. "2017-06-29T12:02:22"), which sorts chronologically
+fn date_key(date: &str) -> Option<&str> {
+    let well_formed = date.len() == 19 && date.bytes().enumerate().all(|(i, b)| match i {
+        4 | 7 => b == b'-',
+        10 => b == b'T',
+        13 | 16 => b == b':',
+        _ => b.is_ascii_digit(),
+    });
+    if well_formed { Some(date) } else { None }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+/// The part of the configuration of the bridge that can be read without a username
+pub struct PublicConfig {
+    /// Name of the bridge. This is also its uPnP name.
+    pub name: String,
+    /// Version of the datastore of the bridge
+    pub datastoreversion: String,
+    /// Software version of the bridge
+    pub swversion: String,
+    /// Version of the hue API on the bridge.
+    pub apiversion: String,
+    /// MAC address of the bridge.
+    pub mac: String,
+    /// The unique bridge id. This is currently generated from the bridge Ethernet MAC address.
+    pub bridgeid: String,
+    /// Whether bridge settings are factory new.
+    pub factorynew: bool,
+    /// If a bridge backup file has been restored on this bridge from a bridge with a different bridgeid, it will indicate that bridge id.
+    pub replacesbridgeid: Option<String>,
+    /// This parameter uniquely identifies the hardware model of the bridge (BSB001, BSB002).
+    pub modelid: String,
+}
+
+#[derive(Debug, Clone, Serialize, Default)]
 /// Information to set about software updates on the bridge
 pub struct SoftwareUpdateModifier {
     /// Lets the bridge search for software updates
-    pub checkforupdate: bool
+    #[serde(skip_serializing_if = "::std::ops::Not::not")]
+    pub checkforupdate: bool,
+    /// Setting this to 3 makes the bridge install the updates it has downloaded
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updatestate: Option<u8>
 }
 
 #[derive(Debug, Clone, Serialize, Default)]
@@ -516,12 +1482,26 @@ pub struct ConfigurationModifier {
     pub touchlink: Option<bool>
 }
 
+impl ConfigurationModifier {
+    /// Changes the proxy the bridge uses to the given address and port, or turns it off for `None`
+    pub fn proxy(proxy: Option<(String, u16)>) -> Self {
+        let (address, port) = proxy.unwrap_or_else(|| ("none".to_owned(), 0));
+        ConfigurationModifier {
+            proxyaddress: Some(address),
+            proxyport: Some(port),
+            ..Default::default()
+        }
+    }
+}
+
 fn null_value() -> JsonValue{
     JsonValue::Null
 }
 
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 /// The entire datastore of the bridge.
+///
+/// It can be serialized, e.g. to save it as a backup, and deserialized again.
 pub struct FullState {
     /// All lights on the bridge.
     pub lights: BTreeMap<usize, Light>,
@@ -542,15 +1522,37 @@ pub struct FullState {
     pub rules: JsonValue
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// Type of a scene
+pub enum SceneType {
+    /// A scene with an explicit list of lights
+    LightScene,
+    /// A scene tied to a group, using the lights of that group
+    GroupScene,
+}
+
+fn string_to_usize_option<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<usize>, D::Error> {
+    use serde::de::Error;
+
+    match <Option<String>>::deserialize(deserializer)? {
+        Some(s) => s.parse().map(Some).map_err(D::Error::custom),
+        None => Ok(None),
+    }
+}
+
+fn usize_option_to_string<S: Serializer>(n: &Option<usize>, serializer: S) -> Result<S::Ok, S::Error> {
+    n.map(|n| n.to_string()).serialize(serializer)
+}
+
 /// A [scene](https://developers.meethue.com/documentation/scenes-api)
 ///
 /// A scene can be used to store a specific set of states of lights on the bridge to recall later.
-#[derive(Debug, Clone, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Scene {
     /// Human readable name given to the scene
     pub name: String,
     /// The IDs of the lights in the scene.
-    #[serde(deserialize_with = "string_to_usize_vec")]
+    #[serde(deserialize_with = "string_to_usize_vec", serialize_with = "usize_vec_to_string")]
     pub lights: Vec<usize>,
     /// The name of the user that created or last modified the scene
     pub owner: String,
@@ -559,24 +1561,70 @@ pub struct Scene {
     /// Whether the scene is locked by a rule or schedule.
     pub locked: bool,
     /// App specific data linked to this scene
-    #[serde(deserialize_with = "non_default")]
+    #[serde(default, deserialize_with = "non_empty")]
     pub appdata: Option<AppData>,
     /// Reserved for future use. See Philips Hue documention
     pub picture: Option<String>,
     /// UTC timestamp of when the scene was last updated
     pub lastupdated: Option<String>,
     /// Light states stored on the scene to be recalled
+    ///
+    /// Entries the bridge left behind for deleted lights may be in here, see `valid_lightstates()`.
+    #[serde(default, deserialize_with = "lenient_lightstates")]
+    pub lightstates: BTreeMap<usize, LightStateChange>,
+    /// Type of the scene. Older bridges don't report it.
+    #[serde(rename="type")]
+    pub scene_type: Option<SceneType>,
+    /// The group the scene belongs to, if it is a `GroupScene`
+    #[serde(default, deserialize_with = "string_to_usize_option", serialize_with = "usize_option_to_string")]
+    pub group: Option<usize>,
+    /// Version of the scene. Older bridges don't report it.
+    ///
+    /// Version 1 scenes store the light states in the lights themselves. Version 2 scenes
+    /// store them on the bridge, but `lightstates` is only filled in when the scene is fetched
+    /// on its own with `get_scene_with_states()`, not by `get_all_scenes()`.
     #[serde(default)]
-    pub lightstates: BTreeMap<usize, LightStateChange>
+    pub version: Option<u8>
+}
+
+impl Scene {
+    /// Converts the stored `lightstates` into commands, so they can be applied to any light
+    pub fn as_light_commands(&self) -> Vec<(usize, LightCommand)> {
+        self.lightstates.iter().map(|(&id, state)| (id, state.into())).collect()
+    }
+    /// The stored `lightstates` of lights that still exist
+    ///
+    /// Light IDs are reused by the bridge, so applying a stale state could change an
+    /// unrelated light that was added later.
+    pub fn valid_lightstates(&self, known: &BTreeMap<usize, Light>) -> BTreeMap<usize, &LightStateChange> {
+        self.lightstates.iter()
+            .filter(|&(id, _)| known.contains_key(id))
+            .map(|(&id, state)| (id, state))
+            .collect()
+    }
+}
+
+/// Skips light states that don't have a numeric ID or aren't objects, as left behind for deleted lights
+fn lenient_lightstates<'de, D>(de: D) -> Result<BTreeMap<usize, LightStateChange>, D::Error>
+where D: Deserializer<'de> {
+    let states = <Option<BTreeMap<String, JsonValue>>>::deserialize(de)?.unwrap_or_default();
+    Ok(states.into_iter()
+        .filter_map(|(id, state)| {
+            let state = ::serde_json::from_value(state).ok()?;
+            id.parse().ok().map(|id| (id, state))
+        })
+        .collect())
 }
 
-fn non_default<'a, 'de, T, D>(de: D) -> Result<Option<T>, D::Error>
-where T: Deserialize<'de> + PartialEq + Default, D: Deserializer<'de> {
-    let ad = <Option<T>>::deserialize(de)?;
-    if ad.as_ref().map(|x| *x == Default::default()).unwrap_or(true) {
-        Ok(None)
-    } else {
-        Ok(ad)
+/// Treats `null` and `{}` as `None`, but keeps objects that explicitly contain default values
+fn non_empty<'de, T, D>(de: D) -> Result<Option<T>, D::Error>
+where T: DeserializeOwned, D: Deserializer<'de> {
+    use serde::de::Error;
+
+    match <Option<JsonMap<String, JsonValue>>>::deserialize(de)? {
+        Some(ref map) if map.is_empty() => Ok(None),
+        Some(map) => ::serde_json::from_value(JsonValue::Object(map)).map(Some).map_err(D::Error::custom),
+        None => Ok(None),
     }
 }
 
@@ -609,9 +1657,23 @@ pub struct SceneCreater {
     pub picture: Option<String>,
     /// Duration of time (in deciseconds) for the lights to transition from one state to another with this scene.
     #[serde(skip_serializing_if = "Option::is_none")]
-    pub transitiontime: Option<u16>
+    pub transitiontime: Option<u16>,
+    /// Type of the scene. Defaults to `LightScene`.
+    #[serde(rename="type")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scene_type: Option<SceneType>,
+    /// The group of a `GroupScene`. The lights of the group are used instead of `lights`.
+    #[serde(skip_serializing_if = "Option::is_none", serialize_with = "usize_option_to_string")]
+    pub group: Option<usize>
 }
-#[derive(Debug, Clone, Serialize)]
+impl SceneCreater {
+    /// Sets the transition time of the scene from a `Duration`, rounded to the closest 100ms
+    pub fn with_transition(self, duration: Duration) -> Self {
+        SceneCreater { transitiontime: Some(deciseconds(duration)), ..self }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
 /// Struct for modifying a scene (renaming, setting lights, updating their state).
 pub struct SceneModifier {
     /// Name to rename the scene to
@@ -624,3 +1686,1086 @@ pub struct SceneModifier {
     #[serde(skip_serializing_if = "::std::ops::Not::not")]
     pub storelightstate: bool
 }
+
+#[derive(Debug, Clone, Deserialize)]
+/// The state of a sensor, only the fields of switches and motion sensors are parsed
+pub struct SensorState {
+    /// The raw code of the last button event of a `ZLLSwitch`, see `button_event()`
+    #[serde(default)]
+    pub buttonevent: Option<u32>,
+    /// Whether a `ZLLPresence` sensor detects motion
+    #[serde(default)]
+    pub presence: Option<bool>,
+    /// When the state was last updated, "none" if it never was
+    #[serde(default)]
+    pub lastupdated: Option<String>,
+}
+
+impl SensorState {
+    /// The last button event of a switch, decoded
+    pub fn button_event(&self) -> Option<ButtonEvent> {
+        self.buttonevent.and_then(ButtonEvent::decode)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// What happened to a button of a switch
+pub enum ButtonAction {
+    /// The button was pressed down
+    InitialPress,
+    /// The button is being held down
+    Hold,
+    /// The button was released after a short press
+    ShortReleased,
+    /// The button was released after being held
+    LongReleased,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// A decoded `buttonevent` of a switch like the Hue dimmer switch
+pub struct ButtonEvent {
+    /// The button, counted from 1
+    pub button: u8,
+    /// What happened to the button
+    pub action: ButtonAction,
+}
+
+impl ButtonEvent {
+    /// Decodes a raw `buttonevent`, e.g. 1002 is a short release of button 1
+    ///
+    /// Codes not following the `button * 1000 + action` encoding, like the ones of the
+    /// Hue Tap, give `None`.
+    pub fn decode(code: u32) -> Option<ButtonEvent> {
+        let action = match code % 1000 {
+            0 => ButtonAction::InitialPress,
+            1 => ButtonAction::Hold,
+            2 => ButtonAction::ShortReleased,
+            3 => ButtonAction::LongReleased,
+            _ => return None,
+        };
+        match code / 1000 {
+            0 => None,
+            button if button <= u32::from(u8::MAX) => Some(ButtonEvent { button: button as u8, action }),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// A condition of a [rule](https://developers.meethue.com/documentation/rules-api)
+pub struct RuleCondition {
+    /// Address of the attribute of a sensor or the configuration, e.g. `/sensors/2/state/buttonevent`
+    pub address: String,
+    /// How the attribute is compared, e.g. "eq", "gt", "lt" or "dx" (changed)
+    pub operator: String,
+    /// The value the attribute is compared with. Not used by "dx".
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+/// An action a rule performs when all of its conditions are met
+pub struct RuleAction {
+    /// The resource the action is sent to, e.g. `/groups/0/action`
+    pub address: String,
+    /// The HTTP method of the request, e.g. "PUT"
+    pub method: String,
+    /// The body of the request
+    pub body: JsonValue,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+/// A rule to be created on the bridge
+pub struct RuleCreator {
+    /// Human readable name of the rule
+    pub name: String,
+    /// Conditions that all have to be met for the rule to trigger
+    pub conditions: Vec<RuleCondition>,
+    /// Actions performed when the rule triggers
+    pub actions: Vec<RuleAction>,
+}
+
+/// Builds the common rules, like recalling a scene when a button is pressed
+///
+/// E.g. `RuleBuilder::on_button_event(2, 34).recall_scene(1, "ab341ef24").build("Tap button 1")`
+#[derive(Debug, Clone, Default)]
+pub struct RuleBuilder {
+    conditions: Vec<RuleCondition>,
+    actions: Vec<RuleAction>,
+}
+
+impl RuleBuilder {
+    /// Triggers when the switch sensor reports the given button event
+    ///
+    /// The `lastupdated` of the sensor is checked as well, so pressing the same button
+    /// twice triggers the rule twice.
+    pub fn on_button_event(sensor_id: usize, buttonevent: u16) -> Self {
+        RuleBuilder::default()
+            .with_condition(RuleCondition {
+                address: format!("/sensors/{}/state/buttonevent", sensor_id),
+                operator: "eq".to_owned(),
+                value: Some(buttonevent.to_string()),
+            })
+            .with_condition(RuleCondition {
+                address: format!("/sensors/{}/state/lastupdated", sensor_id),
+                operator: "dx".to_owned(),
+                value: None,
+            })
+    }
+    /// Adds a condition to the rule
+    pub fn with_condition(mut self, condition: RuleCondition) -> Self {
+        self.conditions.push(condition);
+        self
+    }
+    /// Adds an action to the rule
+    pub fn with_action(mut self, action: RuleAction) -> Self {
+        self.actions.push(action);
+        self
+    }
+    /// Recalls the scene in the group when the rule triggers
+    pub fn recall_scene(self, group_id: usize, scene_id: &str) -> Self {
+        let mut body = JsonMap::new();
+        body.insert("scene".to_owned(), JsonValue::from(scene_id));
+        self.with_action(RuleAction {
+            address: format!("/groups/{}/action", group_id),
+            method: "PUT".to_owned(),
+            body: JsonValue::Object(body),
+        })
+    }
+    /// Sends the command to the group when the rule triggers
+    ///
+    /// Fails if the command can't be serialized.
+    pub fn set_group_state(self, group_id: usize, command: &LightCommand) -> crate::errors::Result<Self> {
+        Ok(self.with_action(RuleAction {
+            address: format!("/groups/{}/action", group_id),
+            method: "PUT".to_owned(),
+            body: ::serde_json::to_value(command)?,
+        }))
+    }
+    /// Creates the rule with the given name
+    pub fn build<S: Into<String>>(self, name: S) -> RuleCreator {
+        RuleCreator { name: name.into(), conditions: self.conditions, actions: self.actions }
+    }
+}
+
+#[cfg(test)]
+pub(crate) const CONFIG_JSON: &str = r#"{
+    "name": "Philips hue",
+    "zigbeechannel": 15,
+    "bridgeid": "001788FFFE100491",
+    "mac": "00:17:88:10:04:91",
+    "dhcp": true,
+    "ipaddress": "192.168.2.7",
+    "netmask": "255.255.255.0",
+    "gateway": "192.168.2.1",
+    "proxyaddress": "none",
+    "proxyport": 0,
+    "UTC": "2017-06-29T12:02:22",
+    "localtime": "2017-06-29T14:02:22",
+    "timezone": "Europe/Amsterdam",
+    "modelid": "BSB002",
+    "datastoreversion": "59",
+    "swversion": "1707040932",
+    "apiversion": "1.19.0",
+    "swupdate": {
+        "updatestate": 0,
+        "checkforupdate": false,
+        "devicetypes": {"bridge": false, "lights": [], "sensors": []},
+        "url": "",
+        "text": "",
+        "notify": true
+    },
+    "swupdate2": {
+        "checkforupdate": false,
+        "lastchange": "2017-06-21T19:44:36",
+        "bridge": {"state": "noupdates", "lastinstall": "2017-06-21T19:44:18"},
+        "state": "anyreadytoinstall",
+        "autoinstall": {"updatetime": "T14:00:00", "on": false}
+    },
+    "internetservices": {
+        "internet": "connected",
+        "remoteaccess": "connected",
+        "time": "connected",
+        "swupdate": "disconnected"
+    },
+    "backup": {"status": "idle", "errorcode": 0},
+    "linkbutton": false,
+    "portalservices": true,
+    "factorynew": false,
+    "replacesbridgeid": null,
+    "whitelist": {
+        "83b7780291a6ceffbe0bd049104df": {
+            "last use date": "2017-06-29T12:02:22",
+            "create date": "2017-01-10T08:30:00",
+            "name": "my_hue_app#homepc"
+        },
+        "ffffffffe0341b1b376a2389376a2389": {
+            "last use date": "2016-03-02T10:00:00",
+            "create date": "2015-12-03T08:57:13",
+            "name": "Hue 2#Samsung SM-G930F"
+        }
+    }
+}"#;
+
+#[test]
+fn configuration_with_swupdate2() {
+    let config: Configuration = ::serde_json::from_str(CONFIG_JSON).unwrap();
+
+    let swupdate2 = config.swupdate2.unwrap();
+    assert_eq!(swupdate2.state, UpdateState::AnyReadyToInstall);
+    assert_eq!(swupdate2.bridge.state, UpdateState::NoUpdates);
+    assert_eq!(swupdate2.autoinstall.updatetime.as_deref(), Some("T14:00:00"));
+    assert!(!swupdate2.autoinstall.on);
+}
+
+#[test]
+fn non_numeric_ids_fail_to_deserialize() {
+    let scene = ::serde_json::from_str::<Scene>(r#"{
+        "name": "Sunset", "group": "living room", "lights": ["1"], "owner": "ffffffffe0341b1b376a2389376a2389",
+        "recycle": false, "locked": false, "picture": "", "lastupdated": null
+    }"#);
+    assert!(scene.is_err());
+
+    let group = ::serde_json::from_str::<Group>(r#"{"name": "Kitchen", "lights": ["1", "two"], "type": "Room"}"#);
+    assert!(group.is_err());
+}
+
+#[test]
+fn configuration_internetservices() {
+    let config: Configuration = ::serde_json::from_str(CONFIG_JSON).unwrap();
+
+    let services = config.internetservices.unwrap();
+    assert_eq!(services.internet, ConnectionStatus::Connected);
+    assert_eq!(services.remoteaccess, ConnectionStatus::Connected);
+    assert_eq!(services.time, ConnectionStatus::Connected);
+    assert_eq!(services.swupdate, ConnectionStatus::Disconnected);
+
+    let mut old: ::serde_json::Value = ::serde_json::from_str(CONFIG_JSON).unwrap();
+    old.as_object_mut().unwrap().remove("internetservices");
+    let config: Configuration = ::serde_json::from_value(old).unwrap();
+    assert!(config.internetservices.is_none());
+}
+
+#[test]
+fn configuration_backup() {
+    let config: Configuration = ::serde_json::from_str(CONFIG_JSON).unwrap();
+    let backup = config.backup.unwrap();
+    assert_eq!(backup.status, BackupState::Idle);
+    assert_eq!(backup.errorcode, 0);
+
+    let backup: BackupStatus = ::serde_json::from_str(r#"{"status": "fileready_disabled", "errorcode": 2}"#).unwrap();
+    assert_eq!(backup.status, BackupState::FileReadyDisabled);
+    assert_eq!(backup.errorcode, 2);
+}
+
+#[test]
+fn whitelist_by_last_use() {
+    let mut config: Configuration = ::serde_json::from_str(CONFIG_JSON).unwrap();
+    let user = |last_use_date: &str| WhitelistUser {
+        name: "app#device".to_owned(),
+        last_use_date: last_use_date.to_owned(),
+        create_date: "2015-12-03T08:57:13".to_owned(),
+    };
+    config.whitelist.insert("broken".to_owned(), user("none"));
+    config.whitelist.insert("oldest".to_owned(), user("2015-12-03T09:00:00"));
+
+    let ids: Vec<_> = config.whitelist_by_last_use().into_iter().map(|(id, _)| &**id).collect();
+    assert_eq!(ids, vec!["oldest", "ffffffffe0341b1b376a2389376a2389", "83b7780291a6ceffbe0bd049104df", "broken"]);
+}
+
+#[test]
+fn configuration_whitelist_user() {
+    let config: Configuration = ::serde_json::from_str(CONFIG_JSON).unwrap();
+
+    let user = config.whitelist_user("83b7780291a6ceffbe0bd049104df").unwrap();
+    assert_eq!(user.name, "my_hue_app#homepc");
+    assert_eq!(user.create_date, "2017-01-10T08:30:00");
+    assert!(config.whitelist_user("revoked").is_none());
+}
+
+#[test]
+fn scene_types() {
+    let scenes: BTreeMap<String, Scene> = ::serde_json::from_str(r#"{
+        "4e1c6b20e-on-0": {
+            "name": "Kathy on 1449133269486",
+            "type": "LightScene",
+            "lights": ["2", "3"],
+            "owner": "ffffffffe0341b1b376a2389376a2389",
+            "recycle": true,
+            "locked": false,
+            "appdata": {},
+            "picture": "",
+            "lastupdated": "2015-12-03T08:57:13",
+            "version": 1
+        },
+        "ab341ef24": {
+            "name": "Sunset",
+            "type": "GroupScene",
+            "group": "1",
+            "lights": ["1", "2"],
+            "owner": "ffffffffe0341b1b376a2389376a2389",
+            "recycle": false,
+            "locked": false,
+            "appdata": {"version": 1, "data": "myAppData"},
+            "picture": "",
+            "lastupdated": "2015-12-03T10:09:22",
+            "version": 2
+        }
+    }"#).unwrap();
+
+    let light_scene = &scenes["4e1c6b20e-on-0"];
+    assert_eq!(light_scene.scene_type, Some(SceneType::LightScene));
+    assert_eq!(light_scene.group, None);
+    let group_scene = &scenes["ab341ef24"];
+    assert_eq!(group_scene.scene_type, Some(SceneType::GroupScene));
+    assert_eq!(group_scene.group, Some(1));
+
+    let creater = SceneCreater {
+        name: "Sunset".to_owned(),
+        lights: Vec::new(),
+        recycle: None,
+        appdata: None,
+        picture: None,
+        transitiontime: None,
+        scene_type: Some(SceneType::GroupScene),
+        group: Some(1),
+    };
+    assert_eq!(::serde_json::to_string(&creater).unwrap(),
+               r#"{"name":"Sunset","lights":[],"type":"GroupScene","group":"1"}"#);
+}
+
+#[test]
+fn light_command_diff_from() {
+    let state: LightState = ::serde_json::from_str(r#"{
+        "on": true,
+        "bri": 144,
+        "hue": 13088,
+        "sat": 212,
+        "xy": [0.5128, 0.4147],
+        "ct": 467,
+        "alert": "none",
+        "effect": "none",
+        "colormode": "xy",
+        "reachable": true
+    }"#).unwrap();
+
+    let noop = LightCommand::default().on().with_bri(144).with_xy((0.51281, 0.4147)).with_transitiontime(10);
+    let diff = noop.diff_from(&state);
+    assert_eq!(::serde_json::to_string(&diff).unwrap(), "{}");
+
+    let cmd = LightCommand::default().on().with_bri(200).with_ct(467);
+    let diff = cmd.diff_from(&state);
+    assert_eq!(::serde_json::to_string(&diff).unwrap(), r#"{"bri":200}"#);
+
+    let off = LightState { on: false, ..state };
+    let diff = LightCommand::default().on().with_bri(144).diff_from(&off);
+    assert_eq!(::serde_json::to_string(&diff).unwrap(), r#"{"on":true}"#);
+}
+
+#[test]
+fn xy_as_array() {
+    let xy = Xy::from((0.4573, 0.41));
+    assert_eq!(::serde_json::to_string(&xy).unwrap(), "[0.4573,0.41]");
+    assert_eq!(::serde_json::from_str::<Xy>("[0.4573,0.41]").unwrap(), xy);
+    assert_eq!(<(f32, f32)>::from(xy), (0.4573, 0.41));
+
+    let cmd = LightCommand::default().with_xy(Xy { x: 0.5, y: 0.25 });
+    assert_eq!(::serde_json::to_string(&cmd).unwrap(), r#"{"xy":[0.5,0.25]}"#);
+}
+
+#[test]
+fn xy_rounded_to_four_decimals() {
+    let cmd = LightCommand::default().with_xy((0.312712, 0.329_04));
+    assert_eq!(::serde_json::to_string(&cmd).unwrap(), r#"{"xy":[0.3127,0.329]}"#);
+    assert_eq!(::serde_json::to_string(&Xy::from((0.67539, 0.0))).unwrap(), "[0.6754,0.0]");
+}
+
+#[test]
+fn light_state_color_summary() {
+    let white: LightState = ::serde_json::from_str(r#"{
+        "on": true, "bri": 254, "alert": "none", "reachable": true
+    }"#).unwrap();
+    assert!(!white.is_color_capable());
+    assert_eq!(white.hue_or(0), 0);
+    assert_eq!(white.color_summary(), ColorSummary::OnOff);
+
+    let tunable: LightState = ::serde_json::from_str(r#"{
+        "on": true, "bri": 254, "ct": 366, "alert": "none", "colormode": "ct", "reachable": true
+    }"#).unwrap();
+    assert!(!tunable.is_color_capable());
+    assert_eq!(tunable.color_summary(), ColorSummary::Temperature(366));
+    assert_eq!(tunable.kelvin(), Some(2732));
+    assert_eq!(white.kelvin(), None);
+
+    let color: LightState = ::serde_json::from_str(r#"{
+        "on": true, "bri": 144, "hue": 13088, "sat": 212, "xy": [0.5128, 0.4147], "ct": 467,
+        "alert": "none", "effect": "none", "colormode": "hs", "reachable": true
+    }"#).unwrap();
+    assert!(color.is_color_capable());
+    assert_eq!(color.hue_or(0), 13088);
+    assert_eq!(color.color_summary(), ColorSummary::Color { hue: 13088, sat: 212 });
+    let color = LightState { colormode: Some("xy".to_owned()), ..color };
+    assert_eq!(color.color_summary(), ColorSummary::ColorXy(Xy { x: 0.5128, y: 0.4147 }));
+}
+
+#[test]
+fn light_command_took_effect() {
+    let state: LightState = ::serde_json::from_str(r#"{
+        "on": true,
+        "bri": 144,
+        "xy": [0.5128, 0.4147],
+        "alert": "none",
+        "reachable": true
+    }"#).unwrap();
+
+    assert!(LightCommand::default().on().with_xy((0.51283, 0.41468)).took_effect(&state));
+    assert!(LightCommand::default().with_bri_inc(20).with_alert(Alert::Select).took_effect(&state));
+    assert!(!LightCommand::default().with_bri(200).took_effect(&state));
+    assert!(!LightCommand::default().with_xy((0.52, 0.4147)).took_effect(&state));
+    assert!(!LightCommand::default().off().took_effect(&state));
+}
+
+#[test]
+fn scene_as_light_commands() {
+    let scene: Scene = ::serde_json::from_str(r#"{
+        "name": "Cozy dinner",
+        "lights": ["1", "2"],
+        "owner": "ffffffffe0341b1b376a2389376a2389",
+        "recycle": false,
+        "locked": false,
+        "appdata": {},
+        "picture": "",
+        "lastupdated": "2015-12-03T10:09:22",
+        "lightstates": {
+            "1": {"on": true, "bri": 237, "xy": [0.5806, 0.3903], "colormode": "xy"},
+            "2": {"on": false}
+        }
+    }"#).unwrap();
+
+    let commands = scene.as_light_commands();
+    assert_eq!(commands.len(), 2);
+    assert_eq!(commands[0].0, 1);
+    assert_eq!(::serde_json::to_string(&commands[0].1).unwrap(), r#"{"on":true,"bri":237,"xy":[0.5806,0.3903]}"#);
+    assert_eq!(commands[1].0, 2);
+    assert_eq!(::serde_json::to_string(&commands[1].1).unwrap(), r#"{"on":false}"#);
+}
+
+#[test]
+fn scene_with_stale_lightstates() {
+    let scene: Scene = ::serde_json::from_str(r#"{
+        "name": "Cozy dinner",
+        "lights": ["1", "2"],
+        "owner": "ffffffffe0341b1b376a2389376a2389",
+        "recycle": false,
+        "locked": false,
+        "appdata": {},
+        "picture": "",
+        "lastupdated": "2015-12-03T10:09:22",
+        "lightstates": {
+            "1": {"on": true, "bri": 237},
+            "2": {"on": false},
+            "7": {"on": true, "bri": 10},
+            "8": null
+        }
+    }"#).unwrap();
+    assert_eq!(scene.lightstates.len(), 3);
+
+    let light: Light = ::serde_json::from_str(r#"{
+        "state": {"on": false, "alert": "none", "reachable": true},
+        "type": "On/Off plug-in unit",
+        "name": "Plug",
+        "modelid": "LOM001",
+        "uniqueid": "00:17:88:01:10:56:4e:6d-0b",
+        "swversion": "1.04.12"
+    }"#).unwrap();
+    let known: BTreeMap<usize, Light> = vec![(1, light.clone()), (3, light)].into_iter().collect();
+
+    let valid = scene.valid_lightstates(&known);
+    assert_eq!(valid.keys().cloned().collect::<Vec<_>>(), vec![1]);
+    assert_eq!(valid[&1].bri, Some(237));
+}
+
+#[test]
+fn light_state_into_light_state_change() {
+    let state: LightState = ::serde_json::from_str(r#"{
+        "on": true,
+        "bri": 144,
+        "hue": 13088,
+        "sat": 212,
+        "xy": [0.5128, 0.4147],
+        "ct": 467,
+        "alert": "none",
+        "effect": "colorloop",
+        "colormode": "xy",
+        "reachable": true
+    }"#).unwrap();
+
+    let change = LightStateChange::from(&state);
+    assert_eq!(change.on, Some(true));
+    assert_eq!(change.bri, Some(144));
+    assert_eq!(change.hue, Some(13088));
+    assert_eq!(change.sat, Some(212));
+    assert_eq!(change.xy, Some(Xy { x: 0.5128, y: 0.4147 }));
+    assert_eq!(change.ct, Some(467));
+    assert_eq!(change.alert.as_deref(), Some("none"));
+    assert_eq!(change.effect.as_deref(), Some("colorloop"));
+    assert_eq!(change.colormode.as_deref(), Some("xy"));
+}
+
+#[test]
+fn discovery() {
+    let discoveries: Vec<Discovery> = ::serde_json::from_str(r#"[
+        {"id": "001788fffe100491", "internalipaddress": "192.168.2.23"},
+        {"id": "001788fffe09a168", "internalipaddress": "192.168.88.252"}
+    ]"#).unwrap();
+
+    assert_eq!(discoveries.len(), 2);
+    assert_eq!(discoveries[0].id(), "001788fffe100491");
+    assert_eq!(discoveries[1].ip(), "192.168.88.252");
+    assert!(discoveries[0].matches_id("001788FFFE100491"));
+    assert!(!discoveries[1].matches_id("001788FFFE100491"));
+}
+
+#[test]
+fn discovery_with_port() {
+    let discoveries: Vec<Discovery> = ::serde_json::from_str(r#"[
+        {"id": "001788fffe100491", "internalipaddress": "192.168.2.23"},
+        {"id": "emulated", "internalipaddress": "192.168.2.40", "port": 8080}
+    ]"#).unwrap();
+
+    assert_eq!(discoveries[0].port, None);
+    assert_eq!(discoveries[0].address(), "192.168.2.23");
+    assert_eq!(discoveries[1].port, Some(8080));
+    assert_eq!(discoveries[1].ip(), "192.168.2.40");
+    assert_eq!(discoveries[1].address(), "192.168.2.40:8080");
+}
+
+#[test]
+fn recyclable_group_creator() {
+    let group = GroupCreator {
+        name: "Temporary".to_owned(),
+        lights: vec![1, 2],
+        group_type: GroupType::LightGroup,
+        class: None,
+        recycle: Some(true),
+    };
+    assert_eq!(::serde_json::to_string(&group).unwrap(),
+               r#"{"name":"Temporary","lights":["1","2"],"type":"LightGroup","recycle":true}"#);
+}
+
+#[test]
+fn public_config() {
+    let config: PublicConfig = ::serde_json::from_str(r#"{
+        "name": "Philips hue",
+        "datastoreversion": "70",
+        "swversion": "1935074050",
+        "apiversion": "1.35.0",
+        "mac": "00:17:88:10:04:91",
+        "bridgeid": "001788FFFE100491",
+        "factorynew": false,
+        "replacesbridgeid": null,
+        "modelid": "BSB002",
+        "starterkitid": ""
+    }"#).unwrap();
+
+    assert_eq!(config.name, "Philips hue");
+    assert_eq!(config.apiversion, "1.35.0");
+    assert_eq!(config.bridgeid, "001788FFFE100491");
+    assert_eq!(config.replacesbridgeid, None);
+}
+
+#[test]
+fn scene_appdata_present_but_empty() {
+    let scene = |appdata: &str| ::serde_json::from_str::<Scene>(&format!(r#"{{
+        "name": "Sunset",
+        "lights": ["1"],
+        "owner": "ffffffffe0341b1b376a2389376a2389",
+        "recycle": false,
+        "locked": false,
+        {}
+        "picture": "",
+        "lastupdated": "2015-12-03T10:09:22"
+    }}"#, appdata)).unwrap().appdata;
+
+    assert_eq!(scene(r#""appdata": {"version": 0, "data": ""},"#), Some(AppData::default()));
+    assert_eq!(scene(r#""appdata": {"version": 1, "data": "x"},"#), Some(AppData { version: 1, data: "x".to_owned() }));
+    assert_eq!(scene(r#""appdata": {},"#), None);
+    assert_eq!(scene(""), None);
+}
+
+#[test]
+fn light_command_merge() {
+    let brightness = LightCommand::default().on().with_bri(100).with_sat_inc(-10);
+    let colour = LightCommand::default().with_bri(200).with_hue(25500).with_bri_inc(5);
+
+    let merged = brightness.merge(colour);
+    assert_eq!(merged.on, Some(true));
+    assert_eq!(merged.bri, Some(200));
+    assert_eq!(merged.hue, Some(25500));
+    assert_eq!(merged.sat_inc, Some(-10));
+    assert_eq!(merged.bri_inc, Some(5));
+    assert_eq!(merged.ct, None);
+}
+
+#[test]
+fn extended_color_light() {
+    let light: Light = ::serde_json::from_str(r#"{
+        "state": {
+            "on": false,
+            "bri": 254,
+            "hue": 8418,
+            "sat": 140,
+            "effect": "none",
+            "xy": [0.4573, 0.41],
+            "ct": 366,
+            "alert": "select",
+            "colormode": "ct",
+            "mode": "homeautomation",
+            "reachable": true
+        },
+        "swupdate": {"state": "noupdates", "lastinstall": "2019-06-17T11:38:36"},
+        "type": "Extended color light",
+        "name": "Hue color lamp 1",
+        "modelid": "LCT015",
+        "manufacturername": "Signify Netherlands B.V.",
+        "productname": "Hue color lamp",
+        "capabilities": {
+            "certified": true,
+            "control": {
+                "mindimlevel": 1000,
+                "maxlumen": 806,
+                "colorgamuttype": "C",
+                "colorgamut": [[0.6915, 0.3083], [0.17, 0.7], [0.1532, 0.0475]],
+                "ct": {"min": 153, "max": 500}
+            },
+            "streaming": {"renderer": true, "proxy": true}
+        },
+        "config": {
+            "archetype": "sultanbulb",
+            "function": "mixed",
+            "direction": "omnidirectional",
+            "startup": {"mode": "safety", "configured": true}
+        },
+        "uniqueid": "00:17:88:01:04:4b:5c:2d-0b",
+        "swversion": "1.46.13_r26312",
+        "swconfigid": "3416C2DD",
+        "productid": "Philips-LCT015-1-A19ECLv5"
+    }"#).unwrap();
+
+    assert_eq!(light.light_type.as_deref(), Some("Extended color light"));
+    assert_eq!(light.manufacturername.as_deref(), Some("Signify Netherlands B.V."));
+    assert_eq!(light.productname.as_deref(), Some("Hue color lamp"));
+    assert_eq!(light.productid.as_deref(), Some("Philips-LCT015-1-A19ECLv5"));
+    assert_eq!(light.capabilities.as_ref().and_then(|c| c.control.colorgamuttype.as_deref()), Some("C"));
+    assert_eq!(color::gamut_for_model(&light.modelid), Some(color::GAMUT_C));
+    let config = light.config.unwrap();
+    assert_eq!(config.archetype.as_deref(), Some("sultanbulb"));
+    assert_eq!(config.function.as_deref(), Some("mixed"));
+    assert_eq!(config.direction.as_deref(), Some("omnidirectional"));
+    let swupdate = light.swupdate.unwrap();
+    assert_eq!(swupdate.state, UpdateState::NoUpdates);
+    assert_eq!(swupdate.lastinstall.as_deref(), Some("2019-06-17T11:38:36"));
+}
+
+#[test]
+fn light_swupdate_ready() {
+    let swupdate: LightSwUpdate = ::serde_json::from_str(r#"{"state": "readytoinstall", "lastinstall": null}"#).unwrap();
+    assert_eq!(swupdate.state, UpdateState::ReadyToInstall);
+    assert_eq!(swupdate.lastinstall, None);
+}
+
+#[test]
+fn on_off_plug() {
+    let light: Light = ::serde_json::from_str(r#"{
+        "state": {"on": true, "alert": "select", "mode": "homeautomation", "reachable": true},
+        "type": "On/Off plug-in unit",
+        "name": "Hue Smart plug 1",
+        "modelid": "LOM001",
+        "manufacturername": "Signify Netherlands B.V.",
+        "productname": "Hue Smart plug",
+        "uniqueid": "00:17:88:01:08:0c:7f:a1-0b",
+        "swversion": "1.65.9_hB3217DF4"
+    }"#).unwrap();
+
+    assert!(light.state.on);
+    assert_eq!(light.state.bri, None);
+    assert_eq!(light.state.hue, None);
+}
+
+#[test]
+fn light_command_presets() {
+    for cmd in &[LightCommand::warm_white(), LightCommand::cool_white(), LightCommand::relax(), LightCommand::concentrate()] {
+        assert_eq!(cmd.on, Some(true));
+        assert!(cmd.bri.map(|b| (1..=254).contains(&b)).unwrap());
+        assert!(cmd.ct.map(|ct| (153..=500).contains(&ct)).unwrap());
+    }
+    for cmd in &[LightCommand::red(), LightCommand::green(), LightCommand::blue()] {
+        assert_eq!(cmd.on, Some(true));
+        assert!(cmd.bri.map(|b| (1..=254).contains(&b)).unwrap());
+        let xy = cmd.xy.unwrap();
+        assert_eq!(color::closest_point_in_gamut(xy, color::GAMUT_C), xy);
+    }
+}
+
+#[test]
+fn group_on_status() {
+    let state = |any_on, all_on| GroupState { any_on, all_on, bri: None, lastupdated: None, lastswitched: None };
+    assert_eq!(state(true, true).status(), GroupOnStatus::AllOn);
+    assert_eq!(state(true, false).status(), GroupOnStatus::Mixed);
+    assert_eq!(state(false, false).status(), GroupOnStatus::AllOff);
+    assert_eq!(state(false, true).status(), GroupOnStatus::AllOff);
+}
+
+#[test]
+fn light_command_equality() {
+    let a = LightCommand::default().on().with_bri(200).with_xy((0.4573, 0.41));
+    let b = LightCommand::default().with_xy((0.4573, 0.41)).with_bri(200).on();
+    assert_eq!(a, b);
+    assert_ne!(a, b.with_transitiontime(4));
+    assert_eq!(LightCommand::warm_white(), LightCommand::warm_white());
+}
+
+#[test]
+fn button_rule() {
+    let rule = RuleBuilder::on_button_event(2, 34)
+        .recall_scene(0, "S3")
+        .build("Tap 2.1 Default");
+    assert_eq!(::serde_json::to_value(&rule).unwrap(), ::serde_json::from_str::<JsonValue>(r#"{
+        "name": "Tap 2.1 Default",
+        "conditions": [
+            {"address": "/sensors/2/state/buttonevent", "operator": "eq", "value": "34"},
+            {"address": "/sensors/2/state/lastupdated", "operator": "dx"}
+        ],
+        "actions": [
+            {"address": "/groups/0/action", "method": "PUT", "body": {"scene": "S3"}}
+        ]
+    }"#).unwrap());
+
+    let rule = RuleBuilder::on_button_event(5, 1002)
+        .set_group_state(3, &LightCommand::default().off())
+        .unwrap()
+        .build("Off");
+    assert_eq!(rule.actions[0].body, ::serde_json::from_str::<JsonValue>(r#"{"on": false}"#).unwrap());
+}
+
+#[test]
+fn transition_from_duration() {
+    let transition = |d| LightCommand::default().with_transition(d).transitiontime;
+    assert_eq!(transition(Duration::from_millis(400)), Some(4));
+    assert_eq!(transition(Duration::from_millis(2500)), Some(25));
+    assert_eq!(transition(Duration::from_millis(449)), Some(4));
+    assert_eq!(transition(Duration::from_millis(450)), Some(5));
+    assert_eq!(transition(Duration::from_secs(10_000)), Some(u16::MAX));
+    assert_eq!(transition(Duration::from_secs(u64::MAX)), Some(u16::MAX));
+
+    let scene = SceneCreater {
+        name: "Fade".to_owned(),
+        lights: vec![1],
+        recycle: None,
+        appdata: None,
+        picture: None,
+        transitiontime: None,
+        scene_type: None,
+        group: None,
+    };
+    assert_eq!(scene.with_transition(Duration::from_secs(3)).transitiontime, Some(30));
+}
+
+#[test]
+fn button_events() {
+    assert_eq!(ButtonEvent::decode(1002), Some(ButtonEvent { button: 1, action: ButtonAction::ShortReleased }));
+    assert_eq!(ButtonEvent::decode(2001), Some(ButtonEvent { button: 2, action: ButtonAction::Hold }));
+    assert_eq!(ButtonEvent::decode(3003), Some(ButtonEvent { button: 3, action: ButtonAction::LongReleased }));
+    assert_eq!(ButtonEvent::decode(4000), Some(ButtonEvent { button: 4, action: ButtonAction::InitialPress }));
+    assert_eq!(ButtonEvent::decode(34), None);
+    assert_eq!(ButtonEvent::decode(1004), None);
+
+    let switch: SensorState = ::serde_json::from_str(r#"{"buttonevent": 4002, "lastupdated": "2017-06-29T12:02:22"}"#).unwrap();
+    assert_eq!(switch.button_event(), Some(ButtonEvent { button: 4, action: ButtonAction::ShortReleased }));
+    assert_eq!(switch.presence, None);
+
+    let motion: SensorState = ::serde_json::from_str(r#"{"presence": true, "lastupdated": "none"}"#).unwrap();
+    assert_eq!(motion.presence, Some(true));
+    assert_eq!(motion.button_event(), None);
+}
+
+#[test]
+fn zigbee_channels() {
+    for &(n, channel) in &[(11, ZigbeeChannel::Ch11), (15, ZigbeeChannel::Ch15), (20, ZigbeeChannel::Ch20),
+                           (25, ZigbeeChannel::Ch25), (0, ZigbeeChannel::Undefined), (13, ZigbeeChannel::Other(13))] {
+        assert_eq!(::serde_json::from_str::<ZigbeeChannel>(&n.to_string()).unwrap(), channel);
+        assert_eq!(channel.channel_number(), n);
+        assert_eq!(::serde_json::to_string(&channel).unwrap(), n.to_string());
+    }
+    let config: Configuration = ::serde_json::from_str(CONFIG_JSON).unwrap();
+    assert_eq!(config.zigbeechannel, ZigbeeChannel::Ch15);
+}
+
+#[test]
+fn scene_versions() {
+    let legacy: Scene = ::serde_json::from_str(r#"{
+        "name": "Kathy on 1449133269486",
+        "lights": ["2", "3"],
+        "owner": "ffffffffe0341b1b376a2389376a2389",
+        "recycle": true,
+        "locked": false,
+        "appdata": {},
+        "picture": "",
+        "lastupdated": "2015-12-03T08:57:13",
+        "version": 1
+    }"#).unwrap();
+    assert_eq!(legacy.version, Some(1));
+
+    let current: Scene = ::serde_json::from_str(r#"{
+        "name": "Cozy dinner",
+        "type": "GroupScene",
+        "group": "1",
+        "lights": ["1", "2"],
+        "owner": "ffffffffe0341b1b376a2389376a2389",
+        "recycle": false,
+        "locked": false,
+        "appdata": {"version": 1, "data": "myAppData"},
+        "picture": "",
+        "lastupdated": "2015-12-03T10:09:22",
+        "version": 2
+    }"#).unwrap();
+    assert_eq!(current.version, Some(2));
+    assert!(current.lightstates.is_empty());
+
+    let unversioned: Scene = ::serde_json::from_str(r#"{
+        "name": "Old", "lights": ["1"], "owner": "none", "recycle": false, "locked": false,
+        "picture": null, "lastupdated": null
+    }"#).unwrap();
+    assert_eq!(unversioned.version, None);
+}
+
+#[test]
+fn group_action() {
+    let group: Group = ::serde_json::from_str(r#"{
+        "name": "Living room",
+        "lights": ["1", "2", "3"],
+        "type": "Room",
+        "state": {"all_on": true, "any_on": true},
+        "recycle": false,
+        "class": "Living room",
+        "action": {
+            "on": true,
+            "bri": 254,
+            "hue": 8402,
+            "sat": 140,
+            "effect": "none",
+            "xy": [0.4573, 0.41],
+            "ct": 366,
+            "alert": "select",
+            "colormode": "xy"
+        }
+    }"#).unwrap();
+
+    let action = group.action.unwrap();
+    assert_eq!(action.on, Some(true));
+    assert_eq!(action.bri, Some(254));
+    assert_eq!(action.xy, Some(Xy { x: 0.4573, y: 0.41 }));
+    assert_eq!(action.ct, Some(366));
+    assert_eq!(action.alert.as_deref(), Some("select"));
+    assert_eq!(action.colormode.as_deref(), Some("xy"));
+}
+
+#[test]
+fn bri_without_on() {
+    let cmd = LightCommand::default().with_bri(100);
+    assert_eq!(cmd.on, None);
+    assert_eq!(::serde_json::to_string(&cmd).unwrap(), r#"{"bri":100}"#);
+    assert_eq!(::serde_json::to_string(&cmd.on()).unwrap(), r#"{"on":true,"bri":100}"#);
+}
+
+#[test]
+fn light_capabilities() {
+    let light: Light = ::serde_json::from_str(r#"{
+        "state": {"on": true, "bri": 254, "hue": 8418, "sat": 140, "xy": [0.4573, 0.41], "ct": 366,
+                  "alert": "none", "colormode": "ct", "reachable": true},
+        "type": "Extended color light",
+        "name": "Hue go",
+        "modelid": "LLC020",
+        "uniqueid": "00:17:88:01:02:3d:1c:8f-0b",
+        "swversion": "1.50.2_r30933",
+        "capabilities": {
+            "certified": true,
+            "control": {
+                "mindimlevel": 40,
+                "maxlumen": 300,
+                "colorgamuttype": "C",
+                "colorgamut": [[0.6915, 0.3083], [0.17, 0.7], [0.1532, 0.0475]],
+                "ct": {"min": 153, "max": 500}
+            },
+            "streaming": {"renderer": true, "proxy": true}
+        }
+    }"#).unwrap();
+
+    let control = &light.capabilities.as_ref().unwrap().control;
+    assert_eq!(control.mindimlevel, Some(40));
+    assert_eq!(control.maxlumen, Some(300));
+    assert_eq!(control.colorgamuttype.as_deref(), Some("C"));
+    assert_eq!(control.ct, Some(CtRange { min: 153, max: 500 }));
+    let gamut = color::Gamut { red: Xy { x: 0.6915, y: 0.3083 }, green: Xy { x: 0.17, y: 0.7 }, blue: Xy { x: 0.1532, y: 0.0475 } };
+    assert_eq!(light.gamut(), Some(gamut));
+
+    let white: LightCapabilitiesInfo = ::serde_json::from_str(r#"{
+        "certified": true, "control": {"mindimlevel": 5000, "maxlumen": 800}
+    }"#).unwrap();
+    assert_eq!(white.control.gamut(), None);
+}
+
+#[test]
+fn scan_states() {
+    let states: Vec<ScanState> = ::serde_json::from_str(r#"["none", "active", "2017-06-29T12:02:22"]"#).unwrap();
+    assert_eq!(states, vec![ScanState::NoScan, ScanState::Active, ScanState::Done("2017-06-29T12:02:22".to_owned())]);
+    assert_eq!(states[2].as_str(), "2017-06-29T12:02:22");
+}
+
+#[test]
+#[cfg(feature = "chrono")]
+fn scan_timestamp() {
+    use chrono::{TimeZone, Utc};
+
+    let done = ScanState::Done("2017-06-29T12:02:22".to_owned());
+    assert_eq!(done.timestamp(), Utc.with_ymd_and_hms(2017, 6, 29, 12, 2, 22).single());
+    assert_eq!(ScanState::Active.timestamp(), None);
+    assert_eq!(ScanState::Done("garbage".to_owned()).timestamp(), None);
+}
+
+#[test]
+fn empty_command() {
+    assert!(LightCommand::default().is_empty());
+    assert!(!LightCommand::default().off().is_empty());
+    assert!(LightCommand::default().with_transition(Duration::from_secs(1)).is_empty());
+    assert!(!LightCommand::default().with_bri(100).with_transition(Duration::from_secs(1)).is_empty());
+}
+
+#[test]
+fn approximately_equal_states() {
+    let state: LightState = ::serde_json::from_str(r#"{
+        "on": true, "bri": 200, "hue": 8418, "sat": 140, "xy": [0.4573, 0.41], "ct": 366,
+        "alert": "none", "colormode": "xy", "reachable": true
+    }"#).unwrap();
+    assert!(state.approx_eq(&state, 0, 0.0));
+
+    let mut jittered = state.clone();
+    jittered.bri = Some(201);
+    jittered.sat = Some(139);
+    jittered.hue = Some(8500);
+    jittered.xy = Some(Xy { x: 0.4575, y: 0.4098 });
+    assert!(state.approx_eq(&jittered, 1, 0.0005));
+    assert!(!state.approx_eq(&jittered, 0, 0.0005));
+    assert!(!state.approx_eq(&jittered, 1, 0.0001));
+
+    let mut dimmed = state.clone();
+    dimmed.bri = Some(150);
+    assert!(!state.approx_eq(&dimmed, 10, 0.01));
+
+    let mut off = state.clone();
+    off.on = false;
+    assert!(!state.approx_eq(&off, 10, 0.01));
+
+    let mut warmer = state.clone();
+    warmer.ct = Some(372);
+    assert!(state.approx_eq(&warmer, 5, 0.0));
+    assert!(!state.approx_eq(&warmer, 4, 0.0));
+
+    let mut red = state.clone();
+    red.hue = Some(65500);
+    let mut wrapped = red.clone();
+    wrapped.hue = Some(100);
+    assert!(red.approx_eq(&wrapped, 1, 0.0));
+    assert!(!red.approx_eq(&state, 10, 0.0));
+}
+
+#[test]
+fn parse_group_types_and_classes() {
+    assert_eq!("Living room".parse::<RoomClass>().unwrap(), RoomClass::LivingRoom);
+    assert_eq!(RoomClass::FrontDoor.to_string().parse::<RoomClass>().unwrap(), RoomClass::FrontDoor);
+    assert!("living room".parse::<RoomClass>().is_err());
+    assert!("Attic".parse::<RoomClass>().is_err());
+
+    assert_eq!("LightGroup".parse::<GroupType>().unwrap(), GroupType::LightGroup);
+    assert_eq!(GroupType::Room.to_string().parse::<GroupType>().unwrap(), GroupType::Room);
+    assert!("Zone".parse::<GroupType>().is_err());
+}
+
+#[test]
+fn full_state_round_trip() {
+    let json = format!(r#"{{
+        "lights": {{
+            "1": {{"state": {{"on": true, "bri": 254, "xy": [0.4573, 0.41], "alert": "none", "reachable": true}},
+                  "type": "Extended color light", "name": "Kitchen", "modelid": "LCT015",
+                  "uniqueid": "00:17:88:01:10:56:4e:6d-0b", "swversion": "1.46.13_r26312"}}
+        }},
+        "groups": {{
+            "1": {{"name": "Kitchen", "lights": ["1"], "type": "Room", "class": "Kitchen",
+                  "state": {{"all_on": true, "any_on": true}}}}
+        }},
+        "config": {},
+        "scenes": {{
+            "4e1c6b20e-on-0": {{"name": "Relax", "lights": ["1"], "owner": "ffffffffe0341b1b376a2389376a2389",
+                               "recycle": false, "locked": false, "picture": "", "lastupdated": null,
+                               "type": "GroupScene", "group": "1", "version": 2}}
+        }}
+    }}"#, CONFIG_JSON);
+    let state: FullState = ::serde_json::from_str(&json).unwrap();
+
+    let exported = ::serde_json::to_string_pretty(&state).unwrap();
+    let restored: FullState = ::serde_json::from_str(&exported).unwrap();
+    assert_eq!(::serde_json::to_string_pretty(&restored).unwrap(), exported);
+    assert_eq!(restored.lights[&1].state, state.lights[&1].state);
+    assert_eq!(restored.groups[&1].lights, vec![1]);
+    assert_eq!(restored.scenes["4e1c6b20e-on-0"].group, Some(1));
+    assert_eq!(restored.config.zigbeechannel, ZigbeeChannel::Ch15);
+    assert_eq!(restored.config.whitelist.len(), 2);
+}
+
+#[test]
+fn room_class_only_for_rooms() {
+    let group = |group_type: &str| ::serde_json::from_str::<Group>(&format!(r#"{{
+        "name": "Group", "lights": ["1"], "type": "{}", "class": "Kitchen"
+    }}"#, group_type)).unwrap();
+    assert_eq!(group("Room").room_class(), Some(RoomClass::Kitchen));
+    assert_eq!(group("LightGroup").room_class(), None);
+}
+
+#[test]
+fn validated_ids() {
+    assert_eq!(usize::from(LightId::try_from("3").unwrap()), 3);
+    assert_eq!(usize::from(LightId::try_from(12).unwrap()), 12);
+    assert!(LightId::try_from(0).is_err());
+    assert!(LightId::try_from("0").is_err());
+    assert!(LightId::try_from("03").is_err());
+    assert!(LightId::try_from("3a").is_err());
+    assert!(LightId::try_from("").is_err());
+
+    assert_eq!(usize::from(GroupId::try_from("0").unwrap()), 0);
+    assert_eq!(GroupId::from(7), GroupId::try_from("7").unwrap());
+    assert!(GroupId::try_from("-1").is_err());
+
+    let scene = SceneId::try_from("4e1c6b20e-on-0").unwrap();
+    assert_eq!(&*scene, "4e1c6b20e-on-0");
+    assert!(SceneId::try_from("AB34EF5").is_ok());
+    assert!(SceneId::try_from("").is_err());
+    assert!(SceneId::try_from("4e1c6b20e/on/0").is_err());
+    assert!(SceneId::try_from("4e1c6b20e-on-0-extra").is_err());
+}
+
+#[test]
+fn white_only_command() {
+    let mut command = LightCommand::default().on().with_bri(200).with_ct(366).with_xy((0.4573, 0.41));
+    command.hue = Some(8418);
+    command.sat = Some(140);
+    command.hue_inc = Some(100);
+    assert_eq!(::serde_json::to_string(&command.white_only()).unwrap(), r#"{"on":true,"bri":200,"ct":366}"#);
+}
+
+#[test]
+fn proxy_modifier() {
+    let set = ConfigurationModifier::proxy(Some(("192.168.2.1".to_owned(), 3128)));
+    assert_eq!(::serde_json::to_string(&set).unwrap(), r#"{"proxyaddress":"192.168.2.1","proxyport":3128}"#);
+    let disable = ConfigurationModifier::proxy(None);
+    assert_eq!(::serde_json::to_string(&disable).unwrap(), r#"{"proxyaddress":"none","proxyport":0}"#);
+}
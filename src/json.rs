@@ -1,4 +1,4 @@
-use crate::errors::HueError;
+use crate::errors::{HueError, HueErrorKind};
 
 
 #[derive(Debug, Deserialize)]
@@ -8,6 +8,26 @@ pub struct User{
     pub username: String
 }
 
+#[derive(Debug, Deserialize)]
+/// A user object returned from the API when a client key was requested as well
+pub struct UserWithClientKey{
+    /// The username of the user
+    pub username: String,
+    /// The key used for streaming to the Entertainment API
+    pub clientkey: String
+}
+
+#[derive(Debug, Deserialize)]
+/// A user object returned from the API when a client key was requested, but the bridge may
+/// be too old to generate one
+pub struct UserInfo{
+    /// The username of the user
+    pub username: String,
+    /// The key used for streaming to the Entertainment API, if the bridge supports it
+    #[serde(default)]
+    pub clientkey: Option<String>
+}
+
 #[derive(Debug, Deserialize)]
 /// An object containing the ID of something newly created
 pub struct Id<T>
@@ -35,9 +55,44 @@ impl<T> HueResponse<T> {
     }
 }
 
+/// Collects the successes of a response, failing with all errors if there were any
+pub fn collect_results<T>(responses: Vec<HueResponse<T>>) -> Result<Vec<T>, HueError> {
+    let mut successes = Vec::with_capacity(responses.len());
+    let mut errors = Vec::new();
+    for response in responses {
+        match response {
+            HueResponse::Success(s) => successes.push(s),
+            HueResponse::Error(e) => errors.push(e),
+        }
+    }
+    if errors.len() > 1 {
+        Err(HueErrorKind::Multiple(errors.into_iter().map(|e| e.code.into()).collect()).into())
+    } else if let Some(e) = errors.pop() {
+        Err(e.into())
+    } else {
+        Ok(successes)
+    }
+}
+
 #[derive(Debug, Clone, Serialize)]
+/// The group action recalling a scene
 pub struct SceneRecall<'a> {
-    pub scene: &'a str
+    /// The ID of the scene to recall
+    pub scene: &'a str,
+    /// Overrides the transition time stored in the scene, in multiples of 100ms
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub transitiontime: Option<u16>
+}
+
+impl<'a> SceneRecall<'a> {
+    /// Recalls the scene as it was stored
+    pub fn new(scene: &'a str) -> Self {
+        SceneRecall { scene, transitiontime: None }
+    }
+    /// Sets the transition time (in multiples of 100ms) to use instead of the one in the scene
+    pub fn with_transitiontime(self, t: u16) -> Self {
+        SceneRecall { transitiontime: Some(t), ..self }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -51,3 +106,54 @@ pub struct Error {
     #[serde(rename="type")]
     pub code: u16,
 }
+
+#[test]
+fn collect_all_errors() {
+    use crate::errors::BridgeError;
+
+    let responses: Vec<HueResponse<::serde_json::Value>> = ::serde_json::from_str(r#"[
+        {"success": {"/lights/1/state/on": true}},
+        {"error": {"type": 7, "address": "/lights/1/state/bri", "description": "invalid value, 300, for parameter, bri"}},
+        {"error": {"type": 6, "address": "/lights/1/state/foo", "description": "parameter, foo, not available"}}
+    ]"#).unwrap();
+
+    match collect_results(responses) {
+        Err(HueError(HueErrorKind::Multiple(errors), _)) => {
+            assert_eq!(errors, vec![BridgeError::InvalidValueForParameter, BridgeError::ParameterNotAvailable])
+        }
+        r => panic!("expected multiple errors, got {:?}", r),
+    }
+}
+
+#[test]
+fn scene_recall_with_transition() {
+    let recall = SceneRecall::new("ab341ef24").with_transitiontime(20);
+    assert_eq!(::serde_json::to_string(&recall).unwrap(), r#"{"scene":"ab341ef24","transitiontime":20}"#);
+    let recall = SceneRecall::new("ab341ef24");
+    assert_eq!(::serde_json::to_string(&recall).unwrap(), r#"{"scene":"ab341ef24"}"#);
+}
+
+#[test]
+fn user_with_clientkey() {
+    let mut responses: Vec<HueResponse<UserWithClientKey>> = ::serde_json::from_str(r#"[
+        {"success": {"username": "83b7780291a6ceffbe0bd049104df", "clientkey": "33DDF493992908E3D97FAAA5A5C5F8E7"}}
+    ]"#).unwrap();
+
+    let user = responses.pop().unwrap().into_result().unwrap();
+    assert_eq!(user.username, "83b7780291a6ceffbe0bd049104df");
+    assert_eq!(user.clientkey, "33DDF493992908E3D97FAAA5A5C5F8E7");
+}
+
+#[test]
+fn user_info_with_and_without_clientkey() {
+    let responses: Vec<HueResponse<UserInfo>> = ::serde_json::from_str(r#"[
+        {"success": {"username": "83b7780291a6ceffbe0bd049104df", "clientkey": "33DDF493992908E3D97FAAA5A5C5F8E7"}},
+        {"success": {"username": "ffffffffe0341b1b376a2389376a2389"}}
+    ]"#).unwrap();
+
+    let users = collect_results(responses).unwrap();
+    assert_eq!(users[0].username, "83b7780291a6ceffbe0bd049104df");
+    assert_eq!(users[0].clientkey.as_deref(), Some("33DDF493992908E3D97FAAA5A5C5F8E7"));
+    assert_eq!(users[1].username, "ffffffffe0341b1b376a2389376a2389");
+    assert_eq!(users[1].clientkey, None);
+}
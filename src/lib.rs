@@ -10,10 +10,13 @@ extern crate error_chain;
 
 pub use crate::bridge::Bridge;
 #[cfg(feature = "nupnp")]
-pub use crate::bridge::discover;
+pub use crate::bridge::{discover, discover_from};
+#[cfg(feature = "async")]
+pub use crate::bridge::{discover_async, discover_from_async};
 #[cfg(feature = "upnp")]
 pub use crate::bridge::discover_upnp;
 pub use crate::hue::LightCommand;
+pub use crate::json::SceneRecall;
 
 /// Errors that can occur in the crate
 pub mod errors;
@@ -21,4 +24,11 @@ pub mod errors;
 pub mod bridge;
 /// Structs mapping the different JSON-objects used with Hue API
 pub mod hue;
+/// Parsing of the successes the bridge responds with
+pub mod success;
+/// Conversions between the colour spaces used by the Hue API
+pub mod color;
+/// Re-exports of the most commonly used items
+pub mod prelude;
 mod json;
+mod rate_limit;
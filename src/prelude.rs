@@ -0,0 +1,13 @@
+//! Re-exports of the most commonly used items, to be glob imported:
+//!
+//! ```
+//! use philipshue::prelude::*;
+//! ```
+
+pub use crate::bridge::Bridge;
+#[cfg(feature = "nupnp")]
+pub use crate::bridge::discover;
+#[cfg(feature = "upnp")]
+pub use crate::bridge::discover_upnp;
+pub use crate::errors::{BridgeError, HueError, HueErrorKind};
+pub use crate::hue::{Discovery, Group, Light, LightCommand, LightState, Scene};
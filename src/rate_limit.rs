@@ -0,0 +1,84 @@
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+#[derive(Debug)]
+/// Token bucket spacing out calls to `wait()` so no more than a given number happen per second
+///
+/// The bucket holds up to `burst` tokens and refills continuously at `per_second` tokens per
+/// second. Every call takes a token, sleeping until one is available if the bucket is empty.
+pub struct RateLimiter {
+    per_second: f64,
+    capacity: f64,
+    /// The tokens in the bucket and when they were counted
+    bucket: Mutex<(f64, Instant)>,
+}
+
+impl RateLimiter {
+    /// Creates a `RateLimiter` allowing `per_second` calls every second, of which up to
+    /// `burst` may happen at once after a quiet period
+    pub fn new(per_second: u32, burst: u32) -> Self {
+        let capacity = f64::from(burst.max(1));
+        RateLimiter {
+            per_second: f64::from(per_second.max(1)),
+            capacity,
+            bucket: Mutex::new((capacity, Instant::now())),
+        }
+    }
+    /// Blocks until the next call is allowed
+    pub fn wait(&self) {
+        let wait = self.reserve(Instant::now());
+        if wait > Duration::from_secs(0) {
+            thread::sleep(wait);
+        }
+    }
+    /// Takes a token for a call at `now`, returning how long the call has to wait for it
+    pub(crate) fn reserve(&self, now: Instant) -> Duration {
+        let mut bucket = self.bucket.lock().unwrap();
+        let (tokens, counted) = *bucket;
+        // Earlier calls may have reserved tokens that are only available in the future
+        let from = counted.max(now);
+        let refilled = now.saturating_duration_since(counted).as_secs_f64() * self.per_second;
+        let tokens = (tokens + refilled).min(self.capacity);
+        if tokens >= 1.0 {
+            *bucket = (tokens - 1.0, from);
+            from - now
+        } else {
+            let ready = from + Duration::from_secs_f64((1.0 - tokens) / self.per_second);
+            *bucket = (0.0, ready);
+            ready - now
+        }
+    }
+}
+
+#[test]
+fn rate_limiter_spaces_calls() {
+    let limiter = RateLimiter::new(50, 1);
+    let start = Instant::now();
+    let waits: Vec<_> = (0..5).map(|_| limiter.reserve(start)).collect();
+    let ms = |ms| Duration::from_millis(ms);
+    assert_eq!(waits, vec![ms(0), ms(20), ms(40), ms(60), ms(80)]);
+
+    // Waiting out the reservations leaves nothing to wait for
+    assert_eq!(limiter.reserve(start + ms(80)), ms(20));
+    assert_eq!(limiter.reserve(start + ms(200)), ms(0));
+}
+
+#[test]
+fn rate_limiter_allows_bursts() {
+    let limiter = RateLimiter::new(50, 5);
+    let start = Instant::now();
+    let ms = |ms| Duration::from_millis(ms);
+    for _ in 0..5 {
+        assert_eq!(limiter.reserve(start), ms(0));
+    }
+    assert_eq!(limiter.reserve(start), ms(20));
+    assert_eq!(limiter.reserve(start), ms(40));
+
+    // A quiet period refills the bucket, but never beyond the burst
+    let later = start + Duration::from_secs(10);
+    for _ in 0..5 {
+        assert_eq!(limiter.reserve(later), ms(0));
+    }
+    assert_eq!(limiter.reserve(later), ms(20));
+}
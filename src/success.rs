@@ -0,0 +1,165 @@
+use std::slice;
+use std::str::FromStr;
+use std::vec;
+
+use serde_json::{Map, Value};
+
+use crate::errors::HueError;
+use crate::json::HueResponse;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A resource that was deleted, parsed from a success like `/lights/3 deleted`
+pub struct Delete {
+    /// The kind of resource that was deleted, e.g. "lights"
+    pub resource: String,
+    /// The ID of the deleted resource
+    pub id: String,
+}
+
+impl FromStr for Delete {
+    type Err = HueError;
+
+    fn from_str(s: &str) -> Result<Self, HueError> {
+        let malformed = || HueError::from(format!("Malformed delete response: {:?}", s));
+
+        let path = s.trim_end_matches(" deleted");
+        if path.len() == s.len() {
+            return Err(malformed());
+        }
+        let mut parts = path.trim_start_matches('/').rsplitn(2, '/');
+        match (parts.next(), parts.next()) {
+            (Some(id), Some(resource)) if !id.is_empty() && !resource.is_empty() => Ok(Delete {
+                resource: resource.to_owned(),
+                id: id.to_owned(),
+            }),
+            _ => Err(malformed()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+/// A user removed from the whitelist, parsed from a success like `/config/whitelist/abc deleted`
+pub struct UserDeleted {
+    /// The username of the deleted user
+    pub username: String,
+}
+
+impl FromStr for UserDeleted {
+    type Err = HueError;
+
+    fn from_str(s: &str) -> Result<Self, HueError> {
+        let delete: Delete = s.parse()?;
+        if delete.resource == "config/whitelist" {
+            Ok(UserDeleted { username: delete.id })
+        } else {
+            Err(format!("Not a deleted user: {:?}", s).into())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+/// A single change the bridge reports as successful, like `{"/lights/1/state/on": true}`
+pub struct State {
+    /// The address of the attribute that was changed, e.g. "/lights/1/state/on"
+    pub address: String,
+    /// The value the attribute was changed to
+    pub value: Value,
+}
+
+#[derive(Debug)]
+/// The successes and errors of a response to a command, in the order the bridge sent them
+///
+/// Iterating over it gives the successes, the errors are in `errors()`.
+pub struct BridgeResponse {
+    successes: Vec<State>,
+    errors: Vec<HueError>,
+}
+
+impl BridgeResponse {
+    pub(crate) fn from_responses(responses: Vec<HueResponse<Map<String, Value>>>) -> Self {
+        let mut successes = Vec::new();
+        let mut errors = Vec::new();
+        for response in responses {
+            match response {
+                HueResponse::Success(changes) => successes.extend(changes.into_iter()
+                    .map(|(address, value)| State { address, value })),
+                HueResponse::Error(e) => errors.push(e.into()),
+            }
+        }
+        BridgeResponse { successes, errors }
+    }
+    /// The changes that were successful
+    pub fn successes(&self) -> &[State] {
+        &self.successes
+    }
+    /// The errors for the parts of the command that failed
+    pub fn errors(&self) -> &[HueError] {
+        &self.errors
+    }
+    /// Whether no part of the command failed
+    pub fn is_ok(&self) -> bool {
+        self.errors.is_empty()
+    }
+}
+
+impl IntoIterator for BridgeResponse {
+    type Item = State;
+    type IntoIter = vec::IntoIter<State>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.successes.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a BridgeResponse {
+    type Item = &'a State;
+    type IntoIter = slice::Iter<'a, State>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.successes.iter()
+    }
+}
+
+#[test]
+fn parse_delete() {
+    let light: Delete = "/lights/3 deleted".parse().unwrap();
+    assert_eq!(light, Delete { resource: "lights".to_owned(), id: "3".to_owned() });
+
+    let group: Delete = "/groups/12 deleted".parse().unwrap();
+    assert_eq!(group.resource, "groups");
+    assert_eq!(group.id, "12");
+
+    assert!("/groups/12".parse::<Delete>().is_err());
+    assert!(" deleted".parse::<Delete>().is_err());
+}
+
+#[test]
+fn parse_user_deleted() {
+    let user: UserDeleted = "/config/whitelist/1028d66426293e821ecfd9ef1a0731df deleted".parse().unwrap();
+    assert_eq!(user.username, "1028d66426293e821ecfd9ef1a0731df");
+
+    assert!("/lights/3 deleted".parse::<UserDeleted>().is_err());
+    assert!("/config/whitelist/abc".parse::<UserDeleted>().is_err());
+}
+
+#[test]
+fn iterate_mixed_response() {
+    use crate::errors::{BridgeError, HueErrorKind};
+
+    let responses = ::serde_json::from_str(r#"[
+        {"success": {"/lights/1/state/on": true}},
+        {"error": {"type": 201, "address": "/lights/1/state/hue", "description": "parameter, hue, is not modifiable. Device is set to off."}},
+        {"success": {"/lights/1/state/bri": 200}}
+    ]"#).unwrap();
+    let response = BridgeResponse::from_responses(responses);
+
+    assert!(!response.is_ok());
+    match *response.errors()[0].kind() {
+        HueErrorKind::BridgeError { error: BridgeError::DeviceIsSetToOff, ref address, .. } => assert_eq!(address, "/lights/1/state/hue"),
+        ref e => panic!("expected device set to off, got {:?}", e),
+    }
+    let addresses: Vec<_> = (&response).into_iter().map(|s| &*s.address).collect();
+    assert_eq!(addresses, vec!["/lights/1/state/on", "/lights/1/state/bri"]);
+    let values: Vec<_> = response.into_iter().map(|s| s.value).collect();
+    assert_eq!(values, vec![Value::Bool(true), Value::from(200)]);
+}